@@ -0,0 +1,150 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::enum_lookup_outcome::LookupOutcome;
+use super::worker_models::ScanControl;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+// Lookups in flight when an adaptive search starts
+const INITIAL_LIMIT: usize = 2;
+
+// Fewest lookups between two adjustments
+const MIN_WINDOW: usize = 10;
+
+struct State {
+    limit: usize,
+    active: usize,
+    peak: usize,
+    latencies: Vec<Duration>,
+    failures: usize,
+    baseline: Option<Duration>, // best median latency seen, i.e. an unloaded resolver
+}
+
+// Caps how many lookups are in flight, tuned from observed latency & error rate (--adaptive)
+// - starts low and ramps up while lookups stay fast and healthy
+// - halves when timeouts & errors rise or latency climbs well above the baseline
+pub struct AdaptiveConcurrency {
+    max: usize,
+    state: Mutex<State>,
+    available: Condvar,
+}
+
+impl AdaptiveConcurrency {
+    pub fn new(max: usize) -> AdaptiveConcurrency {
+        let limit = INITIAL_LIMIT.min(max).max(1);
+        AdaptiveConcurrency {
+            max,
+            state: Mutex::new(State {
+                limit,
+                active: 0,
+                peak: limit,
+                latencies: Vec::new(),
+                failures: 0,
+                baseline: None,
+            }),
+            available: Condvar::new(),
+        }
+    }
+
+    // Block until another lookup may start, returns early if the search is stopped
+    pub fn acquire(&self, control: &ScanControl) {
+        let mut state = self.state.lock().unwrap();
+        while state.active >= state.limit && !control.is_stopped() {
+            state = self
+                .available
+                .wait_timeout(state, Duration::from_millis(50))
+                .unwrap()
+                .0;
+        }
+        state.active += 1;
+    }
+
+    // A lookup finished, adjusting the limit once a window of lookups has been observed
+    pub fn release(&self, latency: Duration, outcome: &LookupOutcome) {
+        let mut state = self.state.lock().unwrap();
+        state.active = state.active.saturating_sub(1);
+        state.latencies.push(latency);
+        if outcome.is_retryable() {
+            state.failures += 1;
+        }
+
+        if state.latencies.len() >= MIN_WINDOW.max(state.limit * 2) {
+            state.latencies.sort();
+            let median = state.latencies[state.latencies.len() / 2];
+            let failure_rate = state.failures as f64 / state.latencies.len() as f64;
+            let baseline = state.baseline.map_or(median, |item| item.min(median));
+
+            state.limit = next_limit(state.limit, self.max, median, failure_rate, baseline);
+            state.peak = state.peak.max(state.limit);
+            state.baseline = Some(baseline);
+            state.latencies.clear();
+            state.failures = 0;
+        }
+
+        self.available.notify_all();
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    pub fn peak(&self) -> usize {
+        self.state.lock().unwrap().peak
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+}
+
+// Util: concurrency limit for the next window of lookups
+fn next_limit(
+    limit: usize,
+    max: usize,
+    median: Duration,
+    failure_rate: f64,
+    baseline: Duration,
+) -> usize {
+    if failure_rate > 0.1 || median > baseline * 3 {
+        (limit / 2).max(1)
+    } else if failure_rate <= 0.02 && median <= baseline * 3 / 2 {
+        (limit + (limit / 4).max(1)).min(max)
+    } else {
+        limit
+    }
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod next_limit_tests {
+    use super::*;
+
+    const BASELINE: Duration = Duration::from_millis(20);
+
+    #[test]
+    fn when_healthy_should_ramp_up_to_max() {
+        assert_eq!(next_limit(2, 64, BASELINE, 0.0, BASELINE), 3);
+        assert_eq!(next_limit(16, 64, BASELINE, 0.01, BASELINE), 20);
+        assert_eq!(next_limit(62, 64, BASELINE, 0.0, BASELINE), 64);
+    }
+
+    #[test]
+    fn when_timeouts_rise_should_back_off() {
+        assert_eq!(next_limit(16, 64, BASELINE, 0.25, BASELINE), 8);
+        assert_eq!(next_limit(1, 64, BASELINE, 1.0, BASELINE), 1);
+    }
+
+    #[test]
+    fn when_latency_climbs_should_back_off() {
+        assert_eq!(next_limit(16, 64, BASELINE * 4, 0.0, BASELINE), 8);
+    }
+
+    #[test]
+    fn when_slightly_degraded_should_hold() {
+        assert_eq!(next_limit(16, 64, BASELINE * 2, 0.05, BASELINE), 16);
+    }
+}
@@ -0,0 +1,153 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::dns_resolver::DnsResolver;
+use super::enum_country_code::CountryCode;
+use super::enum_lookup_outcome::LookupOutcome;
+use super::worker_models::{LookupPolicy, ScanControl};
+use super::{get_vpn_string, lookup_hostname};
+use std::thread;
+
+// Probing gives up past this server number
+const MAX_SERVER_NUMBER: u32 = 65_536;
+
+// Find the highest live server number of a country, None when nothing resolves
+// - probe_window(n) returns the highest live number in n..n + gap_tolerance
+// - exponential search doubles n until a whole window is dead, then binary search narrows down the edge
+// - gaps in the fleet shorter than gap_tolerance are stepped over, longer gaps end the search
+pub fn find_highest_live<F>(start: u32, mut probe_window: F) -> Option<u32>
+where
+    F: FnMut(u32) -> Option<u32>,
+{
+    let mut highest: Option<u32> = None;
+    let mut n = start.max(1);
+
+    // exponential phase, dead windows before the first live server keep doubling
+    let dead = loop {
+        if n > MAX_SERVER_NUMBER {
+            break n;
+        }
+        match probe_window(n) {
+            Some(live) => {
+                highest = Some(highest.map_or(live, |item| item.max(live)));
+                n = n.saturating_mul(2).max(live + 1);
+            }
+            None if highest.is_some() => break n,
+            None => n = n.saturating_mul(2),
+        }
+    };
+
+    // binary phase, between the highest live number and the first dead window
+    let mut lo = highest? + 1;
+    let mut hi = dead;
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        match probe_window(mid) {
+            Some(live) => {
+                highest = Some(live);
+                lo = live + 1;
+            }
+            None => hi = mid,
+        }
+    }
+
+    highest
+}
+
+// Probe a country for its highest live server number, resolving each window in parallel
+pub fn discover_range(
+    country_code: &CountryCode,
+    start: u32,
+    gap_tolerance: u32,
+    thread_count: usize,
+    resolver: &DnsResolver,
+    policy: &LookupPolicy,
+    control: &ScanControl,
+) -> Option<u32> {
+    find_highest_live(start, |window_start| {
+        // a stopped search reads as dead windows, which ends the probing quickly
+        if control.is_stopped() {
+            return None;
+        }
+        let numbers: Vec<u32> =
+            (window_start..window_start.saturating_add(gap_tolerance.max(1))).collect();
+        let chunk_size = numbers.len().div_ceil(thread_count.max(1));
+
+        thread::scope(|s| {
+            let handles: Vec<_> = numbers
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    s.spawn(move || {
+                        chunk
+                            .iter()
+                            .filter(|num| {
+                                let hostname = get_vpn_string(country_code, num);
+                                // only resolved hosts count as live, a failing resolver must not stretch the range
+                                matches!(
+                                    lookup_hostname(resolver, &hostname, policy, control),
+                                    LookupOutcome::Resolved(_)
+                                )
+                            })
+                            .max()
+                            .cloned()
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().unwrap())
+                .max()
+        })
+    })
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod find_highest_live_tests {
+    use super::*;
+
+    // Util: window probe over a fake fleet of live server numbers
+    fn probe_fleet(fleet: &[u32], gap_tolerance: u32) -> impl Fn(u32) -> Option<u32> + '_ {
+        move |n| {
+            fleet
+                .iter()
+                .filter(|num| **num >= n && **num < n + gap_tolerance)
+                .max()
+                .cloned()
+        }
+    }
+
+    #[test]
+    fn when_contiguous_fleet_should_return_last_server() {
+        let test_fleet: Vec<u32> = (1..=642).collect();
+
+        let actual_result = find_highest_live(1, probe_fleet(&test_fleet, 10));
+        assert_eq!(actual_result, Some(642));
+    }
+
+    #[test]
+    fn when_gaps_shorter_than_tolerance_should_step_over_them() {
+        let test_fleet: Vec<u32> = (1..=100).chain(105..=300).chain(308..=320).collect();
+
+        let actual_result = find_highest_live(1, probe_fleet(&test_fleet, 10));
+        assert_eq!(actual_result, Some(320));
+    }
+
+    #[test]
+    fn when_fleet_starts_late_should_still_find_it() {
+        let test_fleet: Vec<u32> = (1000..=1500).collect();
+
+        let actual_result = find_highest_live(1, probe_fleet(&test_fleet, 10));
+        assert_eq!(actual_result, Some(1500));
+    }
+
+    #[test]
+    fn when_nothing_live_should_return_none() {
+        let actual_result = find_highest_live(1, probe_fleet(&[], 10));
+        assert_eq!(actual_result, None);
+    }
+}
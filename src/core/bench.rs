@@ -0,0 +1,193 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::dns_resolver::DnsResolver;
+use super::enum_country_code::CountryCode;
+use super::worker_models::{LookupPolicy, ScanControl};
+use super::{get_vpn_string, lookup_hostname};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Throughput & latency of one concurrency level
+pub struct BenchResult {
+    pub concurrency: usize,
+    pub elapsed: Duration,
+    pub latencies: Vec<Duration>, // sorted
+    pub failures: usize,          // timeouts & errors
+}
+
+impl BenchResult {
+    pub fn lookups_per_sec(&self) -> f64 {
+        self.latencies.len() as f64 / self.elapsed.as_secs_f64().max(0.001)
+    }
+
+    // nearest-rank percentile, E.g. 95.0 for p95
+    pub fn percentile(&self, percent: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::from_millis(0);
+        }
+        let rank = (percent / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        if self.latencies.is_empty() {
+            return 0.0;
+        }
+        self.failures as f64 / self.latencies.len() as f64
+    }
+}
+
+// Resolve the same sample of hostnames with `concurrency` threads
+pub fn bench_level(
+    numbers: &[u32],
+    country_code: &CountryCode,
+    concurrency: usize,
+    resolver: &DnsResolver,
+    policy: &LookupPolicy,
+) -> BenchResult {
+    let control = ScanControl::default();
+    let next = AtomicUsize::new(0);
+    let stop_watch = Instant::now();
+
+    let samples: Vec<(Duration, bool)> = thread::scope(|s| {
+        let handles: Vec<_> = (0..concurrency)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut samples = Vec::new();
+                    while let Some(num) = numbers.get(next.fetch_add(1, Ordering::SeqCst)) {
+                        let hostname = get_vpn_string(country_code, num);
+                        let started = Instant::now();
+                        let outcome = lookup_hostname(resolver, &hostname, policy, &control);
+                        samples.push((started.elapsed(), outcome.is_retryable()));
+                    }
+                    samples
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    let mut latencies: Vec<Duration> = samples.iter().map(|(latency, _)| *latency).collect();
+    latencies.sort();
+
+    BenchResult {
+        concurrency,
+        elapsed: stop_watch.elapsed(),
+        latencies,
+        failures: samples.iter().filter(|(_, failed)| *failed).count(),
+    }
+}
+
+// Util: millisecond column of the results table
+fn format_ms(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+// --Entry Point--
+pub fn entry_point_bench(
+    country_code: CountryCode,
+    sample: u32,
+    levels: Vec<usize>,
+    resolver: DnsResolver,
+    policy: LookupPolicy,
+) -> Result<Vec<BenchResult>, String> {
+    if sample == 0 {
+        return Err(String::from("Sample must be at least 1 hostname"));
+    }
+    if levels.is_empty() || levels.contains(&0) {
+        return Err(String::from("Concurrency levels must be at least 1"));
+    }
+
+    let numbers: Vec<u32> = (1..=sample).collect();
+    println!(
+        "\n⏱  Benchmarking {} with {} hostnames ({}1 - {}{}) per level\n",
+        resolver.nameserver, sample, country_code, country_code, sample
+    );
+    println!("Threads\tLookups/s\tp50\tp95\tp99\tErrors");
+
+    let mut results: Vec<BenchResult> = Vec::new();
+    for concurrency in levels.into_iter() {
+        let result = bench_level(&numbers, &country_code, concurrency, &resolver, &policy);
+        println!(
+            "{}\t{:.1}\t\t{}\t{}\t{}\t{:.1}%",
+            result.concurrency,
+            result.lookups_per_sec(),
+            format_ms(result.percentile(50.0)),
+            format_ms(result.percentile(95.0)),
+            format_ms(result.percentile(99.0)),
+            result.error_rate() * 100.0
+        );
+        results.push(result);
+    }
+
+    // best throughput among the levels that stayed (mostly) error free
+    let best = results
+        .iter()
+        .filter(|result| result.error_rate() <= 0.01)
+        .max_by(|a, b| a.lookups_per_sec().total_cmp(&b.lookups_per_sec()));
+    match best {
+        Some(best) => println!(
+            "\n💡 Best throughput at -t {} ({:.1} lookups/sec)\n",
+            best.concurrency,
+            best.lookups_per_sec()
+        ),
+        None => {
+            println!("\n⚠️  Every level had errors above 1%, the resolver may be rate limiting\n")
+        }
+    }
+
+    Ok(results)
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod bench_result_tests {
+    use super::*;
+
+    fn test_result(latencies_ms: &[u64], failures: usize) -> BenchResult {
+        BenchResult {
+            concurrency: 4,
+            elapsed: Duration::from_secs(2),
+            latencies: latencies_ms
+                .iter()
+                .map(|ms| Duration::from_millis(*ms))
+                .collect(),
+            failures,
+        }
+    }
+
+    #[test]
+    fn when_100_samples_should_return_nearest_rank_percentiles() {
+        let test_latencies: Vec<u64> = (1..=100).collect();
+        let test_result = test_result(&test_latencies, 0);
+
+        assert_eq!(test_result.percentile(50.0), Duration::from_millis(50));
+        assert_eq!(test_result.percentile(95.0), Duration::from_millis(95));
+        assert_eq!(test_result.percentile(99.0), Duration::from_millis(99));
+    }
+
+    #[test]
+    fn when_failures_should_return_error_rate_and_throughput() {
+        let test_result = test_result(&[10, 20, 30, 40], 1);
+
+        assert_eq!(test_result.error_rate(), 0.25);
+        assert_eq!(test_result.lookups_per_sec(), 2.0);
+    }
+
+    #[test]
+    fn when_no_samples_should_return_zero() {
+        let test_result = test_result(&[], 0);
+
+        assert_eq!(test_result.percentile(99.0), Duration::from_millis(0));
+        assert_eq!(test_result.error_rate(), 0.0);
+    }
+}
@@ -0,0 +1,216 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::enum_country_code::CountryCode;
+use super::enum_lookup_outcome::{LookupOutcome, ResolvedHost};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+
+// A completed lookup, as stored in the state file
+pub struct HostRecord {
+    pub country_code: CountryCode,
+    pub num: u32,
+    pub outcome: LookupOutcome,
+}
+
+// State file of completed lookups, one tab separated line per hostname:
+//   au    42    resolved    1.2.3.4,5.6.7.8    lb.nordvpn.com
+//   au    43    nxdomain
+// - timeouts & errors are not recorded, so a resumed search tries them again
+pub struct Checkpoint {
+    completed: Vec<HostRecord>,
+    file: Mutex<File>,
+}
+
+impl Checkpoint {
+    // Start a new state file, replacing any previous one
+    pub fn create(path: &str) -> Result<Checkpoint, String> {
+        let file = File::create(path)
+            .map_err(|err| format!("Unable to create state file {}: {}", path, err))?;
+
+        Ok(Checkpoint::new(file, Vec::new()))
+    }
+
+    // Load the lookups completed by a previous run and keep appending to the same file
+    pub fn resume(path: &str) -> Result<Checkpoint, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| format!("Unable to read state file {}: {}", path, err))?;
+
+        // a run that died mid-write leaves a partial last line, which can still look like a
+        // valid record (1.2.3.4 cut from 1.2.3.45), so it is dropped from the file as well
+        let complete = contents.rfind('\n').map_or(0, |end| end + 1);
+        let completed: Vec<HostRecord> = contents[..complete]
+            .lines()
+            .filter_map(HostRecord::parse)
+            .collect();
+
+        let file = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .and_then(|file| file.set_len(complete as u64).map(|_| file))
+            .map_err(|err| format!("Unable to open state file {}: {}", path, err))?;
+
+        Ok(Checkpoint::new(file, completed))
+    }
+
+    fn new(file: File, completed: Vec<HostRecord>) -> Checkpoint {
+        Checkpoint {
+            completed,
+            file: Mutex::new(file),
+        }
+    }
+
+    // Lookups loaded from the state file, handed over once to the caller
+    pub fn take_completed(&mut self) -> Vec<HostRecord> {
+        std::mem::take(&mut self.completed)
+    }
+
+    // Record a completed lookup, one whole line per write so an exit never leaves half a record
+    pub fn record(&self, country_code: &CountryCode, num: u32, outcome: &LookupOutcome) {
+        let line = match format_record(country_code, num, outcome) {
+            Some(line) => line + "\n",
+            None => return,
+        };
+
+        let mut file = self.file.lock().unwrap();
+        let _ = file.write_all(line.as_bytes());
+    }
+}
+
+impl HostRecord {
+    // None for malformed lines
+    pub fn parse(line: &str) -> Option<HostRecord> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 3 {
+            return None;
+        }
+
+        let country_code = fields[0].parse::<CountryCode>().ok()?;
+        let num = fields[1].parse::<u32>().ok()?;
+
+        let outcome = match fields[2] {
+            "nxdomain" => LookupOutcome::NxDomain,
+            "resolved" if fields.len() >= 4 => {
+                let addresses = fields[3]
+                    .split(',')
+                    .filter(|item| !item.is_empty())
+                    .map(|item| item.parse::<Ipv4Addr>().ok())
+                    .collect::<Option<Vec<Ipv4Addr>>>()?;
+                let cnames = match fields.get(4) {
+                    Some(cnames) if !cnames.is_empty() => {
+                        cnames.split(',').map(|item| item.to_string()).collect()
+                    }
+                    _ => Vec::new(),
+                };
+                LookupOutcome::Resolved(ResolvedHost { addresses, cnames })
+            }
+            _ => return None,
+        };
+
+        Some(HostRecord {
+            country_code,
+            num,
+            outcome,
+        })
+    }
+}
+
+// Util: state file line, None for outcomes that should be retried on resume
+fn format_record(country_code: &CountryCode, num: u32, outcome: &LookupOutcome) -> Option<String> {
+    match outcome {
+        LookupOutcome::Resolved(host) => {
+            let addresses: Vec<String> = host.addresses.iter().map(|ip| ip.to_string()).collect();
+            Some(format!(
+                "{}\t{}\tresolved\t{}\t{}",
+                country_code,
+                num,
+                addresses.join(","),
+                host.cnames.join(",")
+            ))
+        }
+        LookupOutcome::NxDomain => Some(format!("{}\t{}\tnxdomain", country_code, num)),
+        LookupOutcome::Timeout | LookupOutcome::Error(_) => None,
+    }
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod host_record_tests {
+    use super::*;
+
+    #[test]
+    fn when_resolved_should_round_trip() {
+        let test_outcome = LookupOutcome::Resolved(ResolvedHost {
+            addresses: vec![Ipv4Addr::new(1, 2, 3, 4), Ipv4Addr::new(5, 6, 7, 8)],
+            cnames: vec!["lb.nordvpn.com".to_string()],
+        });
+
+        let test_line = format_record(&CountryCode::Au, 42, &test_outcome).unwrap();
+        let actual_result = HostRecord::parse(&test_line).unwrap();
+
+        assert_eq!(actual_result.country_code.to_string(), "au");
+        assert_eq!(actual_result.num, 42);
+        assert_eq!(
+            actual_result.outcome.addresses(),
+            &[Ipv4Addr::new(1, 2, 3, 4), Ipv4Addr::new(5, 6, 7, 8)]
+        );
+    }
+
+    #[test]
+    fn when_no_addresses_should_round_trip() {
+        let test_outcome = LookupOutcome::Resolved(ResolvedHost {
+            addresses: Vec::new(),
+            cnames: vec!["lb.nordvpn.com".to_string()],
+        });
+
+        let test_line = format_record(&CountryCode::Au, 42, &test_outcome).unwrap();
+        let actual_result = HostRecord::parse(&test_line).unwrap();
+
+        assert!(matches!(actual_result.outcome, LookupOutcome::Resolved(_)));
+        assert!(actual_result.outcome.addresses().is_empty());
+    }
+
+    #[test]
+    fn when_nxdomain_should_round_trip() {
+        let test_line = format_record(&CountryCode::De, 7, &LookupOutcome::NxDomain).unwrap();
+        let actual_result = HostRecord::parse(&test_line).unwrap();
+
+        assert!(matches!(actual_result.outcome, LookupOutcome::NxDomain));
+    }
+
+    #[test]
+    fn when_timeout_should_not_be_recorded() {
+        assert!(format_record(&CountryCode::Au, 1, &LookupOutcome::Timeout).is_none());
+    }
+
+    #[test]
+    fn when_resumed_after_partial_last_line_should_drop_it() {
+        let test_path = std::env::temp_dir().join(format!("fns-checkpoint-{}", std::process::id()));
+        let test_path = test_path.to_str().unwrap();
+        std::fs::write(test_path, "au\t41\tnxdomain\nau\t42\tresolved\t1.2.3.4").unwrap();
+
+        let mut test_checkpoint = Checkpoint::resume(test_path).unwrap();
+        test_checkpoint.record(&CountryCode::Au, 43, &LookupOutcome::NxDomain);
+        let actual_contents = std::fs::read_to_string(test_path).unwrap();
+        let _ = std::fs::remove_file(test_path);
+
+        let actual_completed: Vec<u32> = test_checkpoint
+            .take_completed()
+            .iter()
+            .map(|item| item.num)
+            .collect();
+        assert_eq!(actual_completed, vec![41]);
+        assert_eq!(actual_contents, "au\t41\tnxdomain\nau\t43\tnxdomain\n");
+    }
+
+    #[test]
+    fn when_partial_line_should_return_none() {
+        assert!(HostRecord::parse("au\t42\tresolved\t1.2.3").is_none());
+        assert!(HostRecord::parse("au\t4").is_none());
+    }
+}
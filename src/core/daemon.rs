@@ -0,0 +1,964 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::dns_resolver::DnsResolver;
+use super::enum_country_code::CountryCode;
+use super::enum_lookup_outcome::{LookupOutcome, LookupSummary, ResolvedHost};
+use super::enum_match_type::MatchType;
+use super::host_cache::{unix_now, HostCache};
+use super::interrupt::Interrupt;
+use super::output::Output;
+use super::scan::{Scan, ScanEvent, ScanResults};
+use super::worker_models::{Concurrency, LookupPolicy, ScanControl, SearchFilter, WorkerResponse};
+use super::{
+    build_response, format_range_list, parse_country, parse_range_list, print_lookup_summary,
+};
+use indicatif::HumanDuration;
+use std::collections::{HashMap, HashSet};
+use std::fs::DirBuilder;
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::Ipv4Addr;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often a waiting client checks for Ctrl-C, and a waiting query for work it can take over
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// $FNS_SOCKET, else $XDG_RUNTIME_DIR/fns.sock, else /tmp/fns-<uid>/fns.sock
+// - one daemon per user, the socket is only open to its owner
+pub fn daemon_socket_path() -> String {
+    match (
+        std::env::var("FNS_SOCKET"),
+        std::env::var("XDG_RUNTIME_DIR"),
+    ) {
+        (Ok(path), _) if !path.is_empty() => path,
+        (_, Ok(dir)) if !dir.is_empty() => format!("{}/fns.sock", dir.trim_end_matches('/')),
+        _ => format!("/tmp/fns-{}/fns.sock", current_uid()),
+    }
+}
+
+// Util: uid this process runs as
+fn current_uid() -> u32 {
+    // getuid can't fail
+    unsafe { libc::getuid() }
+}
+
+// Util: uid of the process on the other end of a unix socket
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SO_PEERCRED fills in cred, which outlives the call
+    let status = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    match status {
+        0 => Ok(cred.uid),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let mut uid: libc::uid_t = 0;
+    let mut gid: libc::gid_t = 0;
+    // getpeereid fills in uid & gid, which outlive the call
+    match unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } {
+        0 => Ok(uid),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+// Util: only a daemon run by the same user is trusted with a search & its results
+fn check_peer(stream: &UnixStream, socket_path: &str) -> Result<(), String> {
+    let uid = peer_uid(stream)
+        .map_err(|err| format!("Unable to check who owns {}: {}", socket_path, err))?;
+    if uid != current_uid() {
+        return Err(format!(
+            "{} is served by another user (uid {}), use --no-daemon or set $FNS_SOCKET",
+            socket_path, uid
+        ));
+    }
+    Ok(())
+}
+
+// Util: the directory holding the socket, created private to this user if missing
+// - one that isn't owned by this user with mode 700 (E.g. /tmp, or /tmp/fns-<uid> made by someone else) is refused
+fn prepare_socket_dir(socket_path: &str) -> Result<(), String> {
+    let dir = match Path::new(socket_path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !dir.exists() {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .map_err(|err| format!("Unable to create {}: {}", dir.display(), err))?;
+    }
+
+    let metadata = std::fs::metadata(dir)
+        .map_err(|err| format!("Unable to read {}: {}", dir.display(), err))?;
+    if metadata.uid() != current_uid() || metadata.mode() & 0o7777 != 0o700 {
+        return Err(format!(
+            "{} must be owned by this user with mode 700, set $FNS_SOCKET",
+            dir.display()
+        ));
+    }
+    Ok(())
+}
+
+// A search sent to the daemon, one tab separated line:
+//   search    <first 0|1>    <ip,ip>    au=1-1000    de=1-40,250
+struct DaemonQuery {
+    first: bool,
+    targets: Vec<Ipv4Addr>,
+    requests: Vec<(CountryCode, Vec<u32>)>,
+}
+
+impl DaemonQuery {
+    fn format(&self) -> String {
+        let targets: Vec<String> = self.targets.iter().map(|ip| ip.to_string()).collect();
+        let mut fields = vec![
+            String::from("search"),
+            String::from(if self.first { "1" } else { "0" }),
+            targets.join(","),
+        ];
+        for (country_code, numbers) in self.requests.iter() {
+            fields.push(format!("{}={}", country_code, format_range_list(numbers)));
+        }
+        fields.join("\t")
+    }
+
+    fn parse(line: &str) -> Result<DaemonQuery, String> {
+        let fields: Vec<&str> = line.trim_end().split('\t').collect();
+        if fields.len() < 4 || fields[0] != "search" {
+            return Err(format!("Malformed query: {}", line.trim_end()));
+        }
+
+        let targets = fields[2]
+            .split(',')
+            .filter(|item| !item.is_empty())
+            .map(|item| {
+                item.parse::<Ipv4Addr>()
+                    .map_err(|_| format!("Invalid IP Address: {}", item))
+            })
+            .collect::<Result<Vec<Ipv4Addr>, String>>()?;
+
+        let mut requests = Vec::new();
+        for field in fields[3..].iter() {
+            let (country, range_list) = field
+                .split_once('=')
+                .ok_or_else(|| format!("Malformed query: {}", field))?;
+            requests.push((parse_country(country)?, parse_range_list(range_list)?));
+        }
+
+        Ok(DaemonQuery {
+            first: fields[1] == "1",
+            targets,
+            requests,
+        })
+    }
+}
+
+// Util: one lookup outcome sent back to a client, E.g.
+//   result    au    42    resolved    1.2.3.4,5.6.7.8    lb.nordvpn.com
+//   result    au    43    nxdomain
+fn format_result(country_code: &CountryCode, num: u32, outcome: &LookupOutcome) -> String {
+    let outcome = match outcome {
+        LookupOutcome::Resolved(host) => {
+            let addresses: Vec<String> = host.addresses.iter().map(|ip| ip.to_string()).collect();
+            format!(
+                "resolved\t{}\t{}",
+                addresses.join(","),
+                host.cnames.join(",")
+            )
+        }
+        LookupOutcome::NxDomain => String::from("nxdomain"),
+        LookupOutcome::Timeout => String::from("timeout"),
+        LookupOutcome::Error(reason) => format!("error\t{}", reason.replace(['\t', '\n'], " ")),
+    };
+    format!("result\t{}\t{}\t{}", country_code, num, outcome)
+}
+
+fn parse_result(line: &str) -> Result<(CountryCode, u32, LookupOutcome), String> {
+    let malformed = || format!("Malformed result: {}", line.trim_end());
+    let fields: Vec<&str> = line.trim_end_matches(['\r', '\n']).split('\t').collect();
+    if fields.len() < 4 || fields[0] != "result" {
+        return Err(malformed());
+    }
+
+    let country_code = parse_country(fields[1])?;
+    let num = fields[2].parse::<u32>().map_err(|_| malformed())?;
+    let outcome = match (fields[3], fields.get(4), fields.get(5)) {
+        ("resolved", Some(addresses), cnames) => LookupOutcome::Resolved(ResolvedHost {
+            addresses: addresses
+                .split(',')
+                .filter(|item| !item.is_empty())
+                .map(|item| item.parse::<Ipv4Addr>().map_err(|_| malformed()))
+                .collect::<Result<Vec<Ipv4Addr>, String>>()?,
+            cnames: cnames
+                .map(|cnames| {
+                    cnames
+                        .split(',')
+                        .filter(|item| !item.is_empty())
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }),
+        ("nxdomain", _, _) => LookupOutcome::NxDomain,
+        ("timeout", _, _) => LookupOutcome::Timeout,
+        ("error", reason, _) => LookupOutcome::Error(reason.unwrap_or(&"").to_string()),
+        _ => return Err(malformed()),
+    };
+    Ok((country_code, num, outcome))
+}
+
+// A hostname the daemon shares between queries, E.g. ("au", 42)
+type HostKey = (String, u32);
+
+#[derive(Default)]
+struct SharedState {
+    results: HashMap<HostKey, (LookupOutcome, u64)>, // with the unix time it was resolved
+    in_flight: HashSet<HostKey>,                     // being resolved by some query right now
+    pruned: u64,                                     // unix time results were last pruned
+}
+
+impl SharedState {
+    // expired results are dropped at most once per ttl, so a long running daemon doesn't keep every hostname
+    fn insert(&mut self, key: HostKey, outcome: LookupOutcome, seen: u64, ttl: u64) {
+        if seen.saturating_sub(self.pruned) >= ttl {
+            self.results
+                .retain(|_, (_, result_seen)| seen.saturating_sub(*result_seen) < ttl);
+            self.pruned = seen;
+        }
+        self.results.insert(key, (outcome, seen));
+    }
+}
+
+// Owns the cache & every lookup, so concurrent queries for the same hostnames share one lookup
+// - a query claims the hostnames nobody has a fresh result for, and waits for the ones already claimed
+// - timeouts & errors are never shared, whoever asks next tries them again
+struct Daemon {
+    concurrency: Concurrency,
+    resolver: DnsResolver,
+    policy: LookupPolicy,
+    ttl: u64,
+    state: Mutex<SharedState>,
+    changed: Condvar,
+    cache: Mutex<Option<HostCache>>,
+}
+
+// Hostnames a query is resolving, released when it is done with them however that ends
+struct Claim<'a> {
+    daemon: &'a Daemon,
+    keys: Vec<HostKey>,
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        let mut state = self.daemon.state.lock().unwrap();
+        for key in self.keys.iter() {
+            state.in_flight.remove(key);
+        }
+        self.daemon.changed.notify_all();
+    }
+}
+
+// What one query has sent back so far
+struct QueryProgress {
+    sent: HashSet<HostKey>,
+    exact: HashSet<Ipv4Addr>,
+}
+
+impl QueryProgress {
+    fn send(
+        &mut self,
+        out: &mut impl Write,
+        filter: &SearchFilter,
+        country_code: &CountryCode,
+        num: u32,
+        outcome: &LookupOutcome,
+    ) -> io::Result<()> {
+        if !self.sent.insert((country_code.to_string(), num)) {
+            return Ok(());
+        }
+        let item = build_response(country_code, num, outcome.clone(), filter);
+        if let (MatchType::Exact, Some(ip)) = (item.match_type, item.ip) {
+            self.exact.insert(ip);
+        }
+        writeln!(out, "{}", format_result(country_code, num, outcome))
+    }
+
+    // every hostname answered, or every target matched exactly when stopping at the first match
+    fn is_done(&self, filter: &SearchFilter, total: usize) -> bool {
+        self.sent.len() >= total
+            || (filter.first && filter.ips.iter().all(|ip| self.exact.contains(ip)))
+    }
+}
+
+impl Daemon {
+    fn answer(&self, query: &DaemonQuery, out: &mut impl Write) -> Result<(), String> {
+        let hosts: Vec<(CountryCode, u32)> = query
+            .requests
+            .iter()
+            .flat_map(|(country_code, numbers)| {
+                numbers.iter().map(move |num| (country_code.clone(), *num))
+            })
+            .collect();
+        let mut filter = SearchFilter::new(query.targets.clone());
+        filter.first = query.first;
+        let mut progress = QueryProgress {
+            sent: HashSet::new(),
+            exact: HashSet::new(),
+        };
+        let gone = |err: io::Error| format!("Client went away: {}", err);
+
+        while !progress.is_done(&filter, hosts.len()) {
+            let (ready, claim) = {
+                let mut state = self.state.lock().unwrap();
+                let now = unix_now();
+                let mut ready = Vec::new();
+                let mut claimed = Vec::new();
+                for (country_code, num) in hosts.iter() {
+                    let key = (country_code.to_string(), *num);
+                    if progress.sent.contains(&key) {
+                        continue;
+                    }
+                    match state.results.get(&key) {
+                        Some((outcome, seen)) if now.saturating_sub(*seen) < self.ttl => {
+                            ready.push((country_code.clone(), *num, outcome.clone()))
+                        }
+                        _ if state.in_flight.contains(&key) => {}
+                        _ => claimed.push((country_code.clone(), *num)),
+                    }
+                }
+                for (country_code, num) in claimed.iter() {
+                    state.in_flight.insert((country_code.to_string(), *num));
+                }
+                (ready, claimed)
+            };
+
+            for (country_code, num, outcome) in ready.iter() {
+                if progress.is_done(&filter, hosts.len()) {
+                    break;
+                }
+                progress
+                    .send(out, &filter, country_code, *num, outcome)
+                    .map_err(gone)?;
+            }
+            if !claim.is_empty() {
+                self.resolve(claim, &filter, &mut progress, hosts.len(), out)?;
+            } else if ready.is_empty() && !progress.is_done(&filter, hosts.len()) {
+                // everything left is being resolved for another query
+                let state = self.state.lock().unwrap();
+                let _ = self.changed.wait_timeout(state, POLL_INTERVAL).unwrap();
+            }
+        }
+        // with the retries behind any abandoned lookups
+        writeln!(out, "done\t{}", self.policy.retries).map_err(gone)
+    }
+
+    // Scan the claimed hostnames, one country at a time, sharing every result as it arrives
+    fn resolve(
+        &self,
+        claimed: Vec<(CountryCode, u32)>,
+        filter: &SearchFilter,
+        progress: &mut QueryProgress,
+        total: usize,
+        out: &mut impl Write,
+    ) -> Result<(), String> {
+        let _claim = Claim {
+            daemon: self,
+            keys: claimed
+                .iter()
+                .map(|(country_code, num)| (country_code.to_string(), *num))
+                .collect(),
+        };
+
+        let mut countries: Vec<(CountryCode, Vec<u32>)> = Vec::new();
+        for (country_code, num) in claimed.into_iter() {
+            match countries
+                .iter_mut()
+                .find(|(item, _)| item.to_string() == country_code.to_string())
+            {
+                Some((_, numbers)) => numbers.push(num),
+                None => countries.push((country_code, vec![num])),
+            }
+        }
+
+        for (country_code, numbers) in countries.into_iter() {
+            if progress.is_done(filter, total) {
+                break;
+            }
+            let handle = Scan::builder()
+                .country(country_code)
+                .numbers(numbers)
+                .targets(filter.ips.clone())
+                .collect_table(filter.ips.is_empty())
+                .first(filter.first)
+                .concurrency(self.concurrency)
+                .resolver(self.resolver.clone())
+                .policy(self.policy.clone())
+                .build()?
+                .start();
+
+            for event in handle.events().iter() {
+                let item = match event {
+                    ScanEvent::Match(item)
+                    | ScanEvent::LookupDone(item)
+                    | ScanEvent::Error(item) => item,
+                    ScanEvent::Finished(result) => {
+                        result?;
+                        break;
+                    }
+                };
+                self.share(&item);
+                // a client that went away stops its lookups, the ones claimed for others are retried
+                if let Err(err) =
+                    progress.send(out, filter, &item.country_code, item.num, &item.outcome)
+                {
+                    handle.cancel();
+                    return Err(format!("Client went away: {}", err));
+                }
+                if progress.is_done(filter, total) {
+                    handle.cancel();
+                }
+            }
+        }
+        self.save_cache();
+        Ok(())
+    }
+
+    fn share(&self, item: &WorkerResponse) {
+        let now = unix_now();
+        if !item.outcome.is_retryable() {
+            let mut state = self.state.lock().unwrap();
+            state.insert(
+                (item.country_code.to_string(), item.num),
+                item.outcome.clone(),
+                now,
+                self.ttl,
+            );
+            self.changed.notify_all();
+        }
+        if let Some(cache) = self.cache.lock().unwrap().as_mut() {
+            cache.update(&item.country_code, item.num, &item.outcome, now);
+        }
+    }
+
+    // a broken cache only costs the results of a restart, never a query
+    fn save_cache(&self) {
+        if let Some(cache) = self.cache.lock().unwrap().as_ref() {
+            if let Err(err) = cache.save() {
+                eprintln!("warning: {}", err);
+            }
+        }
+    }
+
+    fn handle(&self, stream: UnixStream) {
+        // the socket & its directory are private already, a client that can't be checked is turned away too
+        match peer_uid(&stream) {
+            Ok(uid) if uid == current_uid() => {}
+            _ => {
+                let _ = writeln!(
+                    &stream,
+                    "error\tOnly uid {} can use this daemon",
+                    current_uid()
+                );
+                return;
+            }
+        }
+
+        let stopwatch = Instant::now();
+        let mut reader = BufReader::new(&stream);
+        let mut line = String::new();
+        let mut out = &stream;
+
+        // E.g. another daemon checking whether this one is alive
+        if let Ok(0) = reader.read_line(&mut line) {
+            return;
+        }
+
+        let result = DaemonQuery::parse(&line).and_then(|query| {
+            let hostnames: usize = query.requests.iter().map(|(_, data)| data.len()).sum();
+            println!(
+                "🔎 {} hostnames for {}",
+                hostnames,
+                query
+                    .targets
+                    .iter()
+                    .map(|ip| ip.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            );
+            self.answer(&query, &mut out)
+        });
+        match result {
+            Ok(_) => println!("✅ answered in {}", HumanDuration(stopwatch.elapsed())),
+            Err(err) => {
+                eprintln!("error: {}", err);
+                let _ = writeln!(out, "error\t{}", err.replace(['\t', '\n'], " "));
+            }
+        }
+    }
+}
+
+// Run a search on the daemon, None when no daemon is listening so the search runs locally
+// - lookups are done by the daemon with its own resolver, concurrency & policy
+// - a daemon run by another user is an error, it could answer anything
+pub(super) fn run_on_daemon(
+    socket_path: &str,
+    scan: &mut Scan,
+    control: &ScanControl,
+) -> Option<Result<ScanResults, String>> {
+    let stream = UnixStream::connect(socket_path).ok()?;
+    Some(
+        check_peer(&stream, socket_path)
+            .and_then(|_| query_daemon(socket_path, stream, scan, control)),
+    )
+}
+
+fn query_daemon(
+    socket_path: &str,
+    stream: UnixStream,
+    scan: &mut Scan,
+    control: &ScanControl,
+) -> Result<ScanResults, String> {
+    let stopwatch = Instant::now();
+    let output = scan.output.unwrap_or(Output::Silent);
+    let progress = scan.progress.take().unwrap_or_else(|| output.progress());
+    let query = DaemonQuery {
+        first: scan.filter.first,
+        targets: scan.filter.ips.clone(),
+        requests: scan
+            .requests
+            .iter()
+            .map(|request| (request.country_code.clone(), request.data.clone()))
+            .collect(),
+    };
+    let total: usize = query.requests.iter().map(|(_, data)| data.len()).sum();
+
+    let lost = |err: io::Error| format!("Lost the fns daemon on {}: {}", socket_path, err);
+    writeln!(&stream, "{}", query.format()).map_err(lost)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL)).map_err(lost)?;
+    output.report(&format!(
+        "🛰  Searching {} hostnames with the fns daemon on {}\n",
+        total, socket_path
+    ));
+    progress.started(&[total]);
+
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    let mut responses = Vec::new();
+    let mut summary = LookupSummary::default();
+    let mut policy = scan.policy.clone();
+    let mut result = Ok(());
+    // a timed out read keeps what it got so far, the rest of the line comes with the next one
+    while !control.is_interrupted() {
+        match reader.read_line(&mut line) {
+            Ok(0) => {
+                result = Err(format!(
+                    "The fns daemon on {} closed the connection",
+                    socket_path
+                ));
+                break;
+            }
+            Ok(_) if line.starts_with("done") => {
+                policy.retries = line["done".len()..]
+                    .trim()
+                    .parse()
+                    .unwrap_or(policy.retries);
+                break;
+            }
+            Ok(_) if line.starts_with("error\t") => {
+                result = Err(line["error\t".len()..].trim_end().to_string());
+                break;
+            }
+            Ok(_) => {
+                let (country_code, num, outcome) = match parse_result(&line) {
+                    Ok(parsed) => parsed,
+                    Err(err) => {
+                        result = Err(err);
+                        break;
+                    }
+                };
+                let item = build_response(&country_code, num, outcome, &scan.filter);
+                summary.add(&item.outcome);
+                progress.host_scanned(0, &item.host);
+                if item.ip.is_some() {
+                    progress.matched(&item);
+                }
+                responses.push(item);
+                line.clear();
+            }
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(err) => {
+                result = Err(lost(err));
+                break;
+            }
+        }
+    }
+    progress.finished(&summary);
+    result?;
+
+    print_lookup_summary(output, &summary, &responses, &policy);
+    if control.is_interrupted() {
+        output.report(&format!(
+            "\n⏹  Interrupted, partial results: {} of {} hostnames scanned",
+            summary.total(),
+            total
+        ));
+    }
+    output.report(&format!(
+        "\nElapsed Time: {} ({}ms)\n",
+        HumanDuration(stopwatch.elapsed()),
+        stopwatch.elapsed().as_millis()
+    ));
+
+    Ok(ScanResults {
+        responses,
+        summary,
+        interrupted: control.is_interrupted(),
+        cache_error: None,
+    })
+}
+
+// --Entry Point--
+// - a thread per client, the socket is only open to the user running the daemon
+pub fn entry_point_daemon(
+    socket_path: &str,
+    concurrency: Concurrency,
+    resolver: DnsResolver,
+    policy: LookupPolicy,
+    cache_path: Option<String>,
+    ttl: Duration,
+    interrupt: Interrupt,
+) -> Result<(), String> {
+    prepare_socket_dir(socket_path)?;
+
+    // a socket file nobody answers on is left over from a daemon that was killed
+    if UnixStream::connect(socket_path).is_ok() {
+        return Err(format!(
+            "A fns daemon is already listening on {}",
+            socket_path
+        ));
+    }
+    let _ = std::fs::remove_file(socket_path);
+
+    // the socket is created 600 under a 077 umask, so it's never open to others
+    // - umask only swaps the process mask & can't fail
+    let umask = unsafe { libc::umask(0o077) };
+    let listener = UnixListener::bind(socket_path);
+    unsafe { libc::umask(umask) };
+    let listener =
+        listener.map_err(|err| format!("Unable to listen on {}: {}", socket_path, err))?;
+    std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o600))
+        .map_err(|err| format!("Unable to protect {}: {}", socket_path, err))?;
+
+    // hostnames cached within the ttl are shared straight away
+    let cache = match &cache_path {
+        Some(path) => Some(HostCache::load(path)?),
+        None => None,
+    };
+    let mut state = SharedState::default();
+    if let Some(cache) = &cache {
+        let now = unix_now();
+        for host in cache
+            .hosts()
+            .filter(|host| now.saturating_sub(host.seen) < ttl.as_secs())
+        {
+            let outcome = LookupOutcome::Resolved(ResolvedHost {
+                addresses: host.addresses.clone(),
+                cnames: Vec::new(),
+            });
+            state.results.insert(
+                (host.country_code.to_string(), host.num),
+                (outcome, host.seen),
+            );
+        }
+    }
+
+    println!(
+        "🛰  fns daemon listening on {}, results shared for {} ({} from cache)",
+        socket_path,
+        HumanDuration(ttl),
+        state.results.len()
+    );
+
+    let daemon = Arc::new(Daemon {
+        concurrency,
+        resolver,
+        policy,
+        ttl: ttl.as_secs(),
+        state: Mutex::new(state),
+        changed: Condvar::new(),
+        cache: Mutex::new(cache),
+    });
+
+    // an interrupt wakes the accept loop with a connection of its own, so the socket is removed
+    let stopping = Arc::new(AtomicBool::new(false));
+    let mut listener_interrupt = interrupt.listen();
+    let wake_stopping = stopping.clone();
+    let wake_path = socket_path.to_string();
+    thread::spawn(move || {
+        while !listener_interrupt.wait_timeout(Duration::from_secs(3600)) {}
+        wake_stopping.store(true, Ordering::SeqCst);
+        let _ = UnixStream::connect(&wake_path);
+    });
+
+    for stream in listener.incoming() {
+        if stopping.load(Ordering::SeqCst) {
+            break;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let daemon = daemon.clone();
+        thread::spawn(move || daemon.handle(stream));
+    }
+
+    let _ = std::fs::remove_file(socket_path);
+    println!("⏹  fns daemon stopped");
+    Ok(())
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod daemon_query_tests {
+    use super::*;
+
+    #[test]
+    fn when_formatted_should_parse_back() {
+        let test_query = DaemonQuery {
+            first: true,
+            targets: vec![Ipv4Addr::new(1, 2, 3, 4), Ipv4Addr::new(5, 6, 7, 8)],
+            requests: vec![
+                (CountryCode::Au, vec![1, 2, 3, 250]),
+                (CountryCode::De, vec![7]),
+            ],
+        };
+
+        let test_line = test_query.format();
+        let actual_result = DaemonQuery::parse(&format!("{}\n", test_line)).unwrap();
+
+        assert_eq!(test_line, "search\t1\t1.2.3.4,5.6.7.8\tau=1-3,250\tde=7");
+        assert!(actual_result.first);
+        assert_eq!(actual_result.targets, test_query.targets);
+        assert_eq!(actual_result.requests[0].1, vec![1, 2, 3, 250]);
+        assert_eq!(actual_result.requests[1].0.to_string(), "de");
+    }
+
+    #[test]
+    fn when_malformed_should_throw_error() {
+        assert!(DaemonQuery::parse("search\t1\t1.2.3.4").is_err());
+        assert!(DaemonQuery::parse("search\t1\t1.2.3.4\tzz=1-3").is_err());
+        assert!(DaemonQuery::parse("lookup\t1\t1.2.3.4\tau=1").is_err());
+    }
+}
+
+#[cfg(test)]
+mod result_line_tests {
+    use super::*;
+
+    fn round_trip(outcome: LookupOutcome) -> String {
+        let test_line = format_result(&CountryCode::Au, 42, &outcome);
+        let (country_code, num, actual_result) = parse_result(&format!("{}\n", test_line)).unwrap();
+
+        assert_eq!(format!("{}{}", country_code, num), "au42");
+        actual_result.to_string()
+    }
+
+    #[test]
+    fn when_resolved_should_keep_addresses_and_cnames() {
+        let test_outcome = LookupOutcome::Resolved(ResolvedHost {
+            addresses: vec![Ipv4Addr::new(1, 2, 3, 4), Ipv4Addr::new(5, 6, 7, 8)],
+            cnames: vec![String::from("lb.nordvpn.com")],
+        });
+
+        assert_eq!(
+            round_trip(test_outcome),
+            "resolved 1.2.3.4, 5.6.7.8 via CNAME lb.nordvpn.com"
+        );
+    }
+
+    #[test]
+    fn when_not_resolved_should_keep_outcome() {
+        assert_eq!(round_trip(LookupOutcome::NxDomain), "NXDOMAIN");
+        assert_eq!(round_trip(LookupOutcome::Timeout), "timed out");
+        assert_eq!(
+            round_trip(LookupOutcome::Error(String::from("refused\tby server"))),
+            "error: refused by server"
+        );
+    }
+}
+
+#[cfg(test)]
+mod query_progress_tests {
+    use super::*;
+
+    fn resolved(ip: Ipv4Addr) -> LookupOutcome {
+        LookupOutcome::Resolved(ResolvedHost {
+            addresses: vec![ip],
+            cnames: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn when_first_and_every_ip_matched_should_be_done() {
+        let test_ip = Ipv4Addr::new(1, 2, 3, 4);
+        let test_filter = SearchFilter::new(vec![test_ip]);
+        let mut test_progress = QueryProgress {
+            sent: HashSet::new(),
+            exact: HashSet::new(),
+        };
+        let mut out = Vec::new();
+
+        test_progress
+            .send(
+                &mut out,
+                &test_filter,
+                &CountryCode::Au,
+                1,
+                &resolved(Ipv4Addr::new(1, 2, 3, 5)),
+            )
+            .unwrap();
+        assert!(!test_progress.is_done(&test_filter, 10));
+
+        test_progress
+            .send(
+                &mut out,
+                &test_filter,
+                &CountryCode::Au,
+                2,
+                &resolved(test_ip),
+            )
+            .unwrap();
+        assert!(test_progress.is_done(&test_filter, 10));
+    }
+
+    #[test]
+    fn when_sent_twice_should_only_write_once() {
+        let test_filter = SearchFilter::new(vec![Ipv4Addr::new(1, 2, 3, 4)]);
+        let mut test_progress = QueryProgress {
+            sent: HashSet::new(),
+            exact: HashSet::new(),
+        };
+        let mut out = Vec::new();
+
+        for _ in 0..2 {
+            test_progress
+                .send(
+                    &mut out,
+                    &test_filter,
+                    &CountryCode::Au,
+                    7,
+                    &LookupOutcome::NxDomain,
+                )
+                .unwrap();
+        }
+
+        assert_eq!(String::from_utf8(out).unwrap(), "result\tau\t7\tnxdomain\n");
+        assert!(test_progress.is_done(&test_filter, 1));
+    }
+}
+
+#[cfg(test)]
+mod socket_tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("fns-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn when_socket_dir_missing_should_create_it_private() {
+        let test_dir = test_dir("missing");
+
+        prepare_socket_dir(&format!("{}/fns.sock", test_dir)).unwrap();
+
+        let actual_result = std::fs::metadata(&test_dir).unwrap().mode() & 0o777;
+        let _ = std::fs::remove_dir_all(&test_dir);
+        assert_eq!(actual_result, 0o700);
+    }
+
+    #[test]
+    fn when_socket_dir_writable_by_others_should_throw_error() {
+        let test_dir = test_dir("shared");
+        std::fs::create_dir(&test_dir).unwrap();
+        std::fs::set_permissions(&test_dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+
+        let actual_result = prepare_socket_dir(&format!("{}/fns.sock", test_dir));
+
+        let _ = std::fs::remove_dir_all(&test_dir);
+        assert!(actual_result.is_err());
+    }
+
+    #[test]
+    fn when_socket_dir_sticky_like_tmp_should_throw_error() {
+        let test_dir = test_dir("sticky");
+        std::fs::create_dir(&test_dir).unwrap();
+        std::fs::set_permissions(&test_dir, std::fs::Permissions::from_mode(0o1777)).unwrap();
+
+        let actual_result = prepare_socket_dir(&format!("{}/fns.sock", test_dir));
+
+        let _ = std::fs::remove_dir_all(&test_dir);
+        assert!(actual_result.is_err());
+    }
+
+    #[test]
+    fn when_peer_is_same_user_should_trust_it() {
+        let (test_stream, _other) = UnixStream::pair().unwrap();
+
+        assert_eq!(peer_uid(&test_stream).unwrap(), current_uid());
+        assert!(check_peer(&test_stream, "fns.sock").is_ok());
+    }
+}
+
+#[cfg(test)]
+mod shared_state_tests {
+    use super::*;
+
+    fn test_outcome() -> LookupOutcome {
+        LookupOutcome::Resolved(ResolvedHost {
+            addresses: vec![Ipv4Addr::new(1, 2, 3, 4)],
+            cnames: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn when_results_expired_should_drop_them_on_insert() {
+        let mut test_state = SharedState::default();
+        test_state.insert(("au".to_string(), 1), test_outcome(), 1000, 600);
+        test_state.insert(("au".to_string(), 2), test_outcome(), 1500, 600);
+
+        test_state.insert(("au".to_string(), 3), test_outcome(), 1700, 600);
+
+        let mut actual_result: Vec<u32> = test_state.results.keys().map(|(_, num)| *num).collect();
+        actual_result.sort_unstable();
+        assert_eq!(actual_result, vec![2, 3]);
+    }
+}
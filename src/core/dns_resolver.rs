@@ -0,0 +1,548 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::enum_lookup_outcome::{LookupOutcome, ResolvedHost};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const DNS_PORT: u16 = 53;
+const TYPE_A: u16 = 1;
+const TYPE_CNAME: u16 = 5;
+const TYPE_OPT: u16 = 41;
+const CLASS_IN: u16 = 1;
+const RCODE_NXDOMAIN: u8 = 3;
+const MAX_PACKET_SIZE: usize = 1232;
+
+static QUERY_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Minimal DNS client: sends a single A query over UDP and reports every A record & CNAME hop
+// - EDNS0 asks for answers up to MAX_PACKET_SIZE, a response truncated anyway is asked again over TCP
+#[derive(Clone)]
+pub struct DnsResolver {
+    pub nameserver: SocketAddr,
+}
+
+impl DnsResolver {
+    pub fn new(nameserver: SocketAddr) -> DnsResolver {
+        DnsResolver { nameserver }
+    }
+
+    // Use the first nameserver listed in /etc/resolv.conf
+    pub fn from_system() -> Result<DnsResolver, String> {
+        let resolv_conf = fs::read_to_string("/etc/resolv.conf")
+            .map_err(|err| format!("Unable to read /etc/resolv.conf ({}), use --resolver", err))?;
+
+        match parse_resolv_conf(&resolv_conf) {
+            Some(ip) => Ok(DnsResolver::new(SocketAddr::new(ip, DNS_PORT))),
+            None => Err(String::from(
+                "No nameserver found in /etc/resolv.conf, use --resolver",
+            )),
+        }
+    }
+
+    // Parse "1.1.1.1" or "1.1.1.1:5353" into a resolver
+    pub fn from_address(address: &str) -> Result<DnsResolver, String> {
+        if let Ok(nameserver) = address.parse::<SocketAddr>() {
+            return Ok(DnsResolver::new(nameserver));
+        }
+
+        match address.parse::<IpAddr>() {
+            Ok(ip) => Ok(DnsResolver::new(SocketAddr::new(ip, DNS_PORT))),
+            Err(_) => Err(format!("Invalid resolver address: {}", address)),
+        }
+    }
+
+    // Query the A records of hostname, giving up once the timeout expires
+    pub fn lookup(&self, hostname: &str, timeout: Duration) -> LookupOutcome {
+        let query_id = next_query_id();
+        let query = match build_query(query_id, hostname) {
+            Ok(query) => query,
+            Err(err) => return LookupOutcome::Error(err),
+        };
+
+        let bind_address = if self.nameserver.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+
+        let socket = match UdpSocket::bind(bind_address) {
+            Ok(socket) => socket,
+            Err(err) => return LookupOutcome::Error(format!("unable to open socket: {}", err)),
+        };
+
+        if let Err(err) = socket.send_to(&query, self.nameserver) {
+            return LookupOutcome::Error(format!("unable to reach {}: {}", self.nameserver, err));
+        }
+
+        // wait for our answer, ignoring stray packets until the deadline
+        let deadline = Instant::now() + timeout;
+        let mut buffer = [0u8; MAX_PACKET_SIZE];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) {
+                return LookupOutcome::Timeout;
+            }
+            let _ = socket.set_read_timeout(Some(remaining));
+
+            match socket.recv_from(&mut buffer) {
+                Ok((size, source)) if source == self.nameserver => {
+                    if is_truncated(query_id, &buffer[..size]) {
+                        return self.lookup_tcp(query_id, &query, deadline);
+                    }
+                    match parse_response(query_id, &buffer[..size]) {
+                        Some(outcome) => return outcome,
+                        None => continue,
+                    }
+                }
+                Ok(_) => continue,
+                Err(_) => return LookupOutcome::Timeout,
+            }
+        }
+    }
+
+    // Ask again over TCP, which has room for every A record of a large load balanced hostname
+    fn lookup_tcp(&self, query_id: u16, query: &[u8], deadline: Instant) -> LookupOutcome {
+        let failed = |err: io::Error| match err.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => LookupOutcome::Timeout,
+            _ => LookupOutcome::Error(format!(
+                "unable to reach {} over TCP: {}",
+                self.nameserver, err
+            )),
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining == Duration::from_secs(0) {
+            return LookupOutcome::Timeout;
+        }
+
+        let response =
+            TcpStream::connect_timeout(&self.nameserver, remaining).and_then(|mut stream| {
+                stream.set_read_timeout(Some(remaining))?;
+                stream.set_write_timeout(Some(remaining))?;
+
+                // over TCP every message is prefixed with its length
+                let mut message = (query.len() as u16).to_be_bytes().to_vec();
+                message.extend_from_slice(query);
+                stream.write_all(&message)?;
+
+                let mut length = [0u8; 2];
+                stream.read_exact(&mut length)?;
+                let mut response = vec![0u8; u16::from_be_bytes(length) as usize];
+                stream.read_exact(&mut response)?;
+                Ok(response)
+            });
+
+        match response {
+            Ok(response) => parse_response(query_id, &response).unwrap_or_else(|| {
+                LookupOutcome::Error(String::from("unexpected answer over TCP"))
+            }),
+            Err(err) => failed(err),
+        }
+    }
+}
+
+// Util: first "nameserver" entry of a resolv.conf file
+fn parse_resolv_conf(resolv_conf: &str) -> Option<IpAddr> {
+    resolv_conf
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+        .filter(|fields| fields.len() >= 2 && fields[0] == "nameserver")
+        .find_map(|fields| fields[1].parse::<IpAddr>().ok())
+}
+
+// Util: query ids only need to differ between concurrent queries
+fn next_query_id() -> u16 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos())
+        .unwrap_or(0);
+
+    (nanos as usize ^ QUERY_COUNTER.fetch_add(1, Ordering::Relaxed)) as u16
+}
+
+// Build a recursive query for the A records of hostname
+fn build_query(query_id: u16, hostname: &str) -> Result<Vec<u8>, String> {
+    let mut query = Vec::with_capacity(12 + hostname.len() + 6 + 11);
+
+    // header: id, flags (recursion desired), 1 question, 1 additional record (EDNS0)
+    query.extend_from_slice(&query_id.to_be_bytes());
+    query.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]);
+
+    for label in hostname.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 || !label.is_ascii() {
+            return Err(format!("invalid hostname: {}", hostname));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+
+    query.extend_from_slice(&TYPE_A.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    // OPT record: root owner, our UDP payload size as its class, no extended flags or options
+    query.push(0);
+    query.extend_from_slice(&TYPE_OPT.to_be_bytes());
+    query.extend_from_slice(&(MAX_PACKET_SIZE as u16).to_be_bytes());
+    query.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+
+    Ok(query)
+}
+
+// Util: the answer to our query, cut short because it didn't fit in a UDP packet
+fn is_truncated(query_id: u16, packet: &[u8]) -> bool {
+    packet.len() >= 12
+        && read_u16(packet, 0) == Some(query_id)
+        && packet[2] & 0x80 != 0
+        && packet[2] & 0x02 != 0
+}
+
+// Parse a DNS response into a lookup outcome
+// - None when the packet is not the answer to our query
+fn parse_response(query_id: u16, packet: &[u8]) -> Option<LookupOutcome> {
+    if packet.len() < 12 || read_u16(packet, 0)? != query_id || packet[2] & 0x80 == 0 {
+        return None;
+    }
+
+    // a partial address list would hide the match, so never trust one
+    if packet[2] & 0x02 != 0 {
+        return Some(LookupOutcome::Error(String::from("truncated response")));
+    }
+
+    let rcode = packet[3] & 0x0f;
+    if rcode == RCODE_NXDOMAIN {
+        return Some(LookupOutcome::NxDomain);
+    } else if rcode != 0 {
+        return Some(LookupOutcome::Error(format!(
+            "nameserver returned rcode {}",
+            rcode
+        )));
+    }
+
+    let question_count = read_u16(packet, 4)?;
+    let answer_count = read_u16(packet, 6)?;
+
+    // skip the question section
+    let mut offset = 12;
+    let mut query_name = String::new();
+    for _ in 0..question_count {
+        let (name, next_offset) = read_name(packet, offset)?;
+        query_name = name;
+        offset = next_offset + 4;
+    }
+
+    let mut addresses: Vec<Ipv4Addr> = Vec::new();
+    let mut aliases: HashMap<String, String> = HashMap::new();
+    for _ in 0..answer_count {
+        let (owner, next_offset) = read_name(packet, offset)?;
+        let record_type = read_u16(packet, next_offset)?;
+        let data_length = read_u16(packet, next_offset + 8)? as usize;
+        let data_offset = next_offset + 10;
+        let data = packet.get(data_offset..data_offset + data_length)?;
+
+        match record_type {
+            TYPE_A if data_length == 4 => {
+                addresses.push(Ipv4Addr::new(data[0], data[1], data[2], data[3]))
+            }
+            TYPE_CNAME => {
+                let (target, _) = read_name(packet, data_offset)?;
+                aliases.insert(owner.to_lowercase(), target);
+            }
+            _ => {}
+        }
+        offset = data_offset + data_length;
+    }
+
+    // a name that exists without any A records (NODATA) is resolved, only rcode 3 is NXDOMAIN
+    Some(LookupOutcome::Resolved(ResolvedHost {
+        addresses,
+        cnames: follow_cname_chain(&query_name, &aliases),
+    }))
+}
+
+// Util: walk the alias map from the queried name, e.g. [au1-lb.nordvpn.com, edge.cdn.net]
+fn follow_cname_chain(query_name: &str, aliases: &HashMap<String, String>) -> Vec<String> {
+    let mut chain: Vec<String> = Vec::new();
+    let mut current = query_name.to_lowercase();
+
+    while let Some(target) = aliases.get(&current) {
+        if chain.len() >= aliases.len() {
+            break; // alias loop
+        }
+        chain.push(target.clone());
+        current = target.to_lowercase();
+    }
+
+    chain
+}
+
+fn read_u16(packet: &[u8], offset: usize) -> Option<u16> {
+    let bytes = packet.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+// Util: read a (possibly compressed) domain name, returning it with the offset just past it
+fn read_name(packet: &[u8], offset: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut position = offset;
+    let mut end_offset = None;
+    let mut jumps = 0;
+
+    loop {
+        let length = *packet.get(position)? as usize;
+
+        if length & 0xc0 == 0xc0 {
+            // compression pointer to an earlier name
+            let pointer = (read_u16(packet, position)? & 0x3fff) as usize;
+            end_offset.get_or_insert(position + 2);
+            jumps += 1;
+            if jumps > 16 {
+                return None;
+            }
+            position = pointer;
+        } else if length == 0 {
+            end_offset.get_or_insert(position + 1);
+            break;
+        } else {
+            let label = packet.get(position + 1..position + 1 + length)?;
+            labels.push(String::from_utf8_lossy(label).to_string());
+            position += 1 + length;
+        }
+    }
+
+    Some((labels.join("."), end_offset?))
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod build_query_tests {
+    use super::*;
+
+    #[test]
+    fn when_valid_hostname_should_encode_labels() {
+        let actual_result = build_query(0x1234, "au42.nordvpn.com").unwrap();
+
+        assert_eq!(&actual_result[..2], &[0x12, 0x34]);
+        assert_eq!(&actual_result[12..17], &[4, b'a', b'u', b'4', b'2']);
+        assert_eq!(&actual_result[30..34], &[0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn when_built_should_advertise_edns0_payload_size() {
+        let actual_result = build_query(1, "au42.nordvpn.com").unwrap();
+
+        assert_eq!(&actual_result[10..12], &[0, 1]);
+        assert_eq!(
+            &actual_result[34..],
+            &[0, 0, 41, 0x04, 0xd0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn when_empty_label_should_return_error() {
+        let actual_result = build_query(1, "au42..nordvpn.com");
+
+        assert!(actual_result.is_err());
+    }
+}
+
+#[cfg(test)]
+mod parse_response_tests {
+    use super::*;
+
+    // response header + echoed question for au42.nordvpn.com, without our OPT record
+    fn build_response(query_id: u16, rcode: u8, answer_count: u16) -> Vec<u8> {
+        let mut packet = build_query(query_id, "au42.nordvpn.com").unwrap();
+        packet.truncate(packet.len() - 11);
+        packet[10..12].copy_from_slice(&[0, 0]);
+        packet[2] = 0x81;
+        packet[3] = 0x80 | rcode;
+        packet[6..8].copy_from_slice(&answer_count.to_be_bytes());
+        packet
+    }
+
+    // answer record, owner name given as a pointer to an earlier name
+    fn push_record(packet: &mut Vec<u8>, owner: u16, record_type: u16, data: &[u8]) {
+        packet.extend_from_slice(&(0xc000 | owner).to_be_bytes());
+        packet.extend_from_slice(&record_type.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 60]);
+        packet.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        packet.extend_from_slice(data);
+    }
+
+    #[test]
+    fn when_multiple_a_records_should_return_all_addresses() {
+        let mut test_packet = build_response(7, 0, 2);
+        push_record(&mut test_packet, 12, TYPE_A, &[41, 42, 43, 44]);
+        push_record(&mut test_packet, 12, TYPE_A, &[41, 42, 43, 45]);
+
+        match parse_response(7, &test_packet) {
+            Some(LookupOutcome::Resolved(host)) => {
+                assert_eq!(
+                    host.addresses,
+                    vec![Ipv4Addr::new(41, 42, 43, 44), Ipv4Addr::new(41, 42, 43, 45)]
+                );
+                assert!(host.cnames.is_empty());
+            }
+            _ => panic!("expected a resolved outcome"),
+        }
+    }
+
+    #[test]
+    fn when_cname_chain_should_return_aliases_in_order() {
+        let mut test_packet = build_response(7, 0, 3);
+
+        // au42.nordvpn.com CNAME lb.nordvpn.com (lb + pointer to "nordvpn.com")
+        let alias_offset = test_packet.len() + 12;
+        push_record(&mut test_packet, 12, TYPE_CNAME, &[2, b'l', b'b', 0xc0, 17]);
+        // lb.nordvpn.com CNAME edge.cdn
+        let edge_data = [4, b'e', b'd', b'g', b'e', 3, b'c', b'd', b'n', 0];
+        let edge_offset = test_packet.len() + 12;
+        push_record(
+            &mut test_packet,
+            alias_offset as u16,
+            TYPE_CNAME,
+            &edge_data,
+        );
+        // edge.cdn A 41.42.43.44
+        push_record(
+            &mut test_packet,
+            edge_offset as u16,
+            TYPE_A,
+            &[41, 42, 43, 44],
+        );
+
+        match parse_response(7, &test_packet) {
+            Some(LookupOutcome::Resolved(host)) => {
+                assert_eq!(host.addresses, vec![Ipv4Addr::new(41, 42, 43, 44)]);
+                assert_eq!(host.cnames, vec!["lb.nordvpn.com", "edge.cdn"]);
+            }
+            _ => panic!("expected a resolved outcome"),
+        }
+    }
+
+    #[test]
+    fn when_rcode_nxdomain_should_return_nxdomain() {
+        let test_packet = build_response(7, RCODE_NXDOMAIN, 0);
+
+        let actual_result = parse_response(7, &test_packet);
+        assert!(matches!(actual_result, Some(LookupOutcome::NxDomain)));
+    }
+
+    #[test]
+    fn when_no_a_records_should_return_resolved_without_addresses() {
+        let mut test_packet = build_response(7, 0, 1);
+        push_record(&mut test_packet, 12, TYPE_CNAME, &[2, b'l', b'b', 0xc0, 17]);
+
+        match parse_response(7, &test_packet) {
+            Some(LookupOutcome::Resolved(host)) => {
+                assert!(host.addresses.is_empty());
+                assert_eq!(host.cnames, vec!["lb.nordvpn.com"]);
+            }
+            _ => panic!("expected a resolved outcome"),
+        }
+    }
+
+    #[test]
+    fn when_truncated_should_return_error() {
+        let mut test_packet = build_response(7, 0, 1);
+        test_packet[2] |= 0x02;
+        push_record(&mut test_packet, 12, TYPE_A, &[41, 42, 43, 44]);
+
+        assert!(is_truncated(7, &test_packet));
+        let actual_result = parse_response(7, &test_packet);
+        assert!(matches!(actual_result, Some(LookupOutcome::Error(_))));
+    }
+
+    #[test]
+    fn when_rcode_servfail_should_return_error() {
+        let test_packet = build_response(7, 2, 0);
+
+        let actual_result = parse_response(7, &test_packet);
+        assert!(matches!(actual_result, Some(LookupOutcome::Error(_))));
+    }
+
+    #[test]
+    fn when_query_id_differs_should_return_none() {
+        let test_packet = build_response(7, 0, 0);
+
+        assert!(parse_response(8, &test_packet).is_none());
+    }
+}
+
+#[cfg(test)]
+mod lookup_tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    // the query echoed back as an answer, without the OPT record
+    fn answer(query: &[u8], truncated: bool, addresses: &[[u8; 4]]) -> Vec<u8> {
+        let mut packet = query[..query.len() - 11].to_vec();
+        packet[2] = if truncated { 0x83 } else { 0x81 };
+        packet[3] = 0x80;
+        packet[6..8].copy_from_slice(&(addresses.len() as u16).to_be_bytes());
+        packet[10..12].copy_from_slice(&[0, 0]);
+        for address in addresses.iter() {
+            packet.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4]);
+            packet.extend_from_slice(address);
+        }
+        packet
+    }
+
+    #[test]
+    fn when_udp_answer_truncated_should_retry_over_tcp() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let nameserver = tcp.local_addr().unwrap();
+        let udp = UdpSocket::bind(nameserver).unwrap();
+
+        thread::spawn(move || {
+            let mut buffer = [0u8; MAX_PACKET_SIZE];
+            let (size, client) = udp.recv_from(&mut buffer).unwrap();
+            let _ = udp.send_to(&answer(&buffer[..size], true, &[[1, 1, 1, 1]]), client);
+        });
+        thread::spawn(move || {
+            let (mut stream, _) = tcp.accept().unwrap();
+            let mut length = [0u8; 2];
+            stream.read_exact(&mut length).unwrap();
+            let mut query = vec![0u8; u16::from_be_bytes(length) as usize];
+            stream.read_exact(&mut query).unwrap();
+
+            let response = answer(&query, false, &[[1, 1, 1, 1], [1, 1, 1, 2], [1, 1, 1, 3]]);
+            let mut message = (response.len() as u16).to_be_bytes().to_vec();
+            message.extend_from_slice(&response);
+            stream.write_all(&message).unwrap();
+        });
+
+        let actual_result =
+            DnsResolver::new(nameserver).lookup("au42.nordvpn.com", Duration::from_secs(5));
+        assert_eq!(actual_result.addresses().len(), 3);
+    }
+}
+
+#[cfg(test)]
+mod parse_resolv_conf_tests {
+    use super::*;
+
+    #[test]
+    fn when_nameserver_listed_should_return_first() {
+        let test_resolv_conf =
+            "# generated\nsearch lan\nnameserver 10.0.0.53\nnameserver 1.1.1.1\n";
+
+        let actual_result = parse_resolv_conf(test_resolv_conf);
+        assert_eq!(actual_result, Some("10.0.0.53".parse::<IpAddr>().unwrap()));
+    }
+
+    #[test]
+    fn when_no_nameserver_should_return_none() {
+        assert_eq!(parse_resolv_conf("search lan\n"), None);
+    }
+}
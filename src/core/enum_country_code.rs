@@ -69,68 +69,134 @@ pub enum CountryCode {
     Empty,
 }
 
+impl CountryCode {
+    // Every country with NordVPN servers, for sweeps across all countries
+    pub fn all() -> Vec<CountryCode> {
+        vec![
+            CountryCode::Al,
+            CountryCode::Ar,
+            CountryCode::Au,
+            CountryCode::At,
+            CountryCode::Be,
+            CountryCode::Ba,
+            CountryCode::Br,
+            CountryCode::Bg,
+            CountryCode::Ca,
+            CountryCode::Cl,
+            CountryCode::Cr,
+            CountryCode::Hr,
+            CountryCode::Cy,
+            CountryCode::Cz,
+            CountryCode::Dk,
+            CountryCode::Ee,
+            CountryCode::Fi,
+            CountryCode::Fr,
+            CountryCode::Ge,
+            CountryCode::De,
+            CountryCode::Gr,
+            CountryCode::Hk,
+            CountryCode::Hu,
+            CountryCode::Is,
+            CountryCode::In,
+            CountryCode::Id,
+            CountryCode::Ie,
+            CountryCode::Il,
+            CountryCode::It,
+            CountryCode::Jp,
+            CountryCode::Lv,
+            CountryCode::Lu,
+            CountryCode::My,
+            CountryCode::Mx,
+            CountryCode::Md,
+            CountryCode::Nl,
+            CountryCode::Nz,
+            CountryCode::Mk,
+            CountryCode::No,
+            CountryCode::Pl,
+            CountryCode::Pt,
+            CountryCode::Ro,
+            CountryCode::Rs,
+            CountryCode::Sg,
+            CountryCode::Sk,
+            CountryCode::Si,
+            CountryCode::Za,
+            CountryCode::Kr,
+            CountryCode::Es,
+            CountryCode::Se,
+            CountryCode::Ch,
+            CountryCode::Tw,
+            CountryCode::Th,
+            CountryCode::Tr,
+            CountryCode::Ua,
+            CountryCode::Uk,
+            CountryCode::Us,
+            CountryCode::Vn,
+        ]
+    }
+}
+
 impl Display for CountryCode {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match *self {
-            CountryCode::Al => write!(f, "{}", "al"),
-            CountryCode::Ar => write!(f, "{}", "ar"),
-            CountryCode::Au => write!(f, "{}", "au"),
-            CountryCode::At => write!(f, "{}", "at"),
-            CountryCode::Be => write!(f, "{}", "be"),
-            CountryCode::Ba => write!(f, "{}", "ba"),
-            CountryCode::Br => write!(f, "{}", "br"),
-            CountryCode::Bg => write!(f, "{}", "bg"),
-            CountryCode::Ca => write!(f, "{}", "ca"),
-            CountryCode::Cl => write!(f, "{}", "cl"),
-            CountryCode::Cr => write!(f, "{}", "cr"),
-            CountryCode::Hr => write!(f, "{}", "hr"),
-            CountryCode::Cy => write!(f, "{}", "cy"),
-            CountryCode::Cz => write!(f, "{}", "cz"),
-            CountryCode::Dk => write!(f, "{}", "dk"),
-            CountryCode::Ee => write!(f, "{}", "ee"),
-            CountryCode::Fi => write!(f, "{}", "fi"),
-            CountryCode::Fr => write!(f, "{}", "fr"),
-            CountryCode::Ge => write!(f, "{}", "ge"),
-            CountryCode::De => write!(f, "{}", "de"),
-            CountryCode::Gr => write!(f, "{}", "gr"),
-            CountryCode::Hk => write!(f, "{}", "hk"),
-            CountryCode::Hu => write!(f, "{}", "hu"),
-            CountryCode::Is => write!(f, "{}", "is"),
-            CountryCode::In => write!(f, "{}", "in"),
-            CountryCode::Id => write!(f, "{}", "id"),
-            CountryCode::Ie => write!(f, "{}", "ie"),
-            CountryCode::Il => write!(f, "{}", "il"),
-            CountryCode::It => write!(f, "{}", "it"),
-            CountryCode::Jp => write!(f, "{}", "jp"),
-            CountryCode::Lv => write!(f, "{}", "lv"),
-            CountryCode::Lu => write!(f, "{}", "lu"),
-            CountryCode::My => write!(f, "{}", "my"),
-            CountryCode::Mx => write!(f, "{}", "mx"),
-            CountryCode::Md => write!(f, "{}", "md"),
-            CountryCode::Nl => write!(f, "{}", "nl"),
-            CountryCode::Nz => write!(f, "{}", "nz"),
-            CountryCode::Mk => write!(f, "{}", "mk"),
-            CountryCode::No => write!(f, "{}", "no"),
-            CountryCode::Pl => write!(f, "{}", "pl"),
-            CountryCode::Pt => write!(f, "{}", "pt"),
-            CountryCode::Ro => write!(f, "{}", "ro"),
-            CountryCode::Rs => write!(f, "{}", "rs"),
-            CountryCode::Sg => write!(f, "{}", "sg"),
-            CountryCode::Sk => write!(f, "{}", "sk"),
-            CountryCode::Si => write!(f, "{}", "si"),
-            CountryCode::Za => write!(f, "{}", "za"),
-            CountryCode::Kr => write!(f, "{}", "kr"),
-            CountryCode::Es => write!(f, "{}", "es"),
-            CountryCode::Se => write!(f, "{}", "se"),
-            CountryCode::Ch => write!(f, "{}", "ch"),
-            CountryCode::Tw => write!(f, "{}", "tw"),
-            CountryCode::Th => write!(f, "{}", "th"),
-            CountryCode::Tr => write!(f, "{}", "tr"),
-            CountryCode::Ua => write!(f, "{}", "ua"),
-            CountryCode::Uk => write!(f, "{}", "uk"),
-            CountryCode::Us => write!(f, "{}", "us"),
-            CountryCode::Vn => write!(f, "{}", "vn"),
-            CountryCode::Empty => write!(f, "{}", ""),
+            CountryCode::Al => write!(f, "al"),
+            CountryCode::Ar => write!(f, "ar"),
+            CountryCode::Au => write!(f, "au"),
+            CountryCode::At => write!(f, "at"),
+            CountryCode::Be => write!(f, "be"),
+            CountryCode::Ba => write!(f, "ba"),
+            CountryCode::Br => write!(f, "br"),
+            CountryCode::Bg => write!(f, "bg"),
+            CountryCode::Ca => write!(f, "ca"),
+            CountryCode::Cl => write!(f, "cl"),
+            CountryCode::Cr => write!(f, "cr"),
+            CountryCode::Hr => write!(f, "hr"),
+            CountryCode::Cy => write!(f, "cy"),
+            CountryCode::Cz => write!(f, "cz"),
+            CountryCode::Dk => write!(f, "dk"),
+            CountryCode::Ee => write!(f, "ee"),
+            CountryCode::Fi => write!(f, "fi"),
+            CountryCode::Fr => write!(f, "fr"),
+            CountryCode::Ge => write!(f, "ge"),
+            CountryCode::De => write!(f, "de"),
+            CountryCode::Gr => write!(f, "gr"),
+            CountryCode::Hk => write!(f, "hk"),
+            CountryCode::Hu => write!(f, "hu"),
+            CountryCode::Is => write!(f, "is"),
+            CountryCode::In => write!(f, "in"),
+            CountryCode::Id => write!(f, "id"),
+            CountryCode::Ie => write!(f, "ie"),
+            CountryCode::Il => write!(f, "il"),
+            CountryCode::It => write!(f, "it"),
+            CountryCode::Jp => write!(f, "jp"),
+            CountryCode::Lv => write!(f, "lv"),
+            CountryCode::Lu => write!(f, "lu"),
+            CountryCode::My => write!(f, "my"),
+            CountryCode::Mx => write!(f, "mx"),
+            CountryCode::Md => write!(f, "md"),
+            CountryCode::Nl => write!(f, "nl"),
+            CountryCode::Nz => write!(f, "nz"),
+            CountryCode::Mk => write!(f, "mk"),
+            CountryCode::No => write!(f, "no"),
+            CountryCode::Pl => write!(f, "pl"),
+            CountryCode::Pt => write!(f, "pt"),
+            CountryCode::Ro => write!(f, "ro"),
+            CountryCode::Rs => write!(f, "rs"),
+            CountryCode::Sg => write!(f, "sg"),
+            CountryCode::Sk => write!(f, "sk"),
+            CountryCode::Si => write!(f, "si"),
+            CountryCode::Za => write!(f, "za"),
+            CountryCode::Kr => write!(f, "kr"),
+            CountryCode::Es => write!(f, "es"),
+            CountryCode::Se => write!(f, "se"),
+            CountryCode::Ch => write!(f, "ch"),
+            CountryCode::Tw => write!(f, "tw"),
+            CountryCode::Th => write!(f, "th"),
+            CountryCode::Tr => write!(f, "tr"),
+            CountryCode::Ua => write!(f, "ua"),
+            CountryCode::Uk => write!(f, "uk"),
+            CountryCode::Us => write!(f, "us"),
+            CountryCode::Vn => write!(f, "vn"),
+            CountryCode::Empty => write!(f, ""),
         }
     }
 }
@@ -0,0 +1,135 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::enum_match_type::MatchType;
+use super::worker_models::WorkerResponse;
+
+// Process exit codes so scripts can branch on the outcome of a search
+pub enum ExitCode {
+    Success,      // a subcommand that isn't a search finished without error
+    ExactMatch,   // 0
+    PartialMatch, // 1
+    NoMatch,      // 2
+    Error,        // 3
+}
+
+impl ExitCode {
+    // Best match found across all results: any exact match wins over partial matches
+    // - no match is only definite when every hostname was looked up, an interrupted sweep or
+    //   abandoned lookups could have hidden the match, so they are an error
+    pub fn from_results(results: &[WorkerResponse], interrupted: bool) -> ExitCode {
+        let mut exit_code = ExitCode::NoMatch;
+
+        for item in results.iter() {
+            match item.match_type {
+                MatchType::Exact => return ExitCode::ExactMatch,
+                MatchType::Partial => exit_code = ExitCode::PartialMatch,
+                MatchType::None => {}
+            }
+        }
+
+        let incomplete = interrupted || results.iter().any(|item| item.outcome.is_retryable());
+        match exit_code {
+            ExitCode::NoMatch if incomplete => ExitCode::Error,
+            _ => exit_code,
+        }
+    }
+
+    pub fn code(self) -> i32 {
+        match self {
+            ExitCode::Success | ExitCode::ExactMatch => 0,
+            ExitCode::PartialMatch => 1,
+            ExitCode::NoMatch => 2,
+            ExitCode::Error => 3,
+        }
+    }
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod exit_code_tests {
+    use super::super::enum_country_code::CountryCode;
+    use super::super::enum_lookup_outcome::{LookupOutcome, ResolvedHost};
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn build_response(match_type: MatchType) -> WorkerResponse {
+        WorkerResponse {
+            country_code: CountryCode::Au,
+            num: 42,
+            match_type,
+            host: "au42.nordvpn.com".to_string(),
+            ip: Some(Ipv4Addr::new(1, 2, 3, 4)),
+            outcome: LookupOutcome::Resolved(ResolvedHost {
+                addresses: vec![Ipv4Addr::new(1, 2, 3, 4)],
+                cnames: Vec::new(),
+            }),
+        }
+    }
+
+    #[test]
+    fn when_no_results_should_return_no_match() {
+        let actual_result = ExitCode::from_results(&[], false);
+        assert_eq!(actual_result.code(), 2);
+    }
+
+    #[test]
+    fn when_only_unmatched_results_should_return_no_match() {
+        let test_results = vec![build_response(MatchType::None)];
+
+        let actual_result = ExitCode::from_results(&test_results, false);
+        assert_eq!(actual_result.code(), 2);
+    }
+
+    #[test]
+    fn when_only_partial_results_should_return_partial_match() {
+        let test_results = vec![build_response(MatchType::Partial)];
+
+        let actual_result = ExitCode::from_results(&test_results, false);
+        assert_eq!(actual_result.code(), 1);
+    }
+
+    #[test]
+    fn when_exact_and_partial_results_should_return_exact_match() {
+        let test_results = vec![
+            build_response(MatchType::Partial),
+            build_response(MatchType::Exact),
+        ];
+
+        let actual_result = ExitCode::from_results(&test_results, false);
+        assert_eq!(actual_result.code(), 0);
+    }
+
+    #[test]
+    fn when_interrupted_without_match_should_return_error() {
+        let test_results = vec![build_response(MatchType::None)];
+
+        let actual_result = ExitCode::from_results(&test_results, true);
+        assert_eq!(actual_result.code(), 3);
+    }
+
+    #[test]
+    fn when_lookups_abandoned_without_match_should_return_error() {
+        let mut test_response = build_response(MatchType::None);
+        test_response.outcome = LookupOutcome::Timeout;
+
+        let actual_result = ExitCode::from_results(&[test_response], false);
+        assert_eq!(actual_result.code(), 3);
+    }
+
+    #[test]
+    fn when_interrupted_after_partial_match_should_return_partial_match() {
+        let test_results = vec![build_response(MatchType::Partial)];
+
+        let actual_result = ExitCode::from_results(&test_results, true);
+        assert_eq!(actual_result.code(), 1);
+    }
+
+    #[test]
+    fn when_success_should_return_zero() {
+        assert_eq!(ExitCode::Success.code(), 0);
+    }
+}
@@ -0,0 +1,92 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::net::Ipv4Addr;
+
+// Every A record of a hostname, plus the CNAME hops taken to reach them
+#[derive(Clone)]
+pub struct ResolvedHost {
+    pub addresses: Vec<Ipv4Addr>,
+    pub cnames: Vec<String>,
+}
+
+// Final outcome of looking up a single hostname (after retries)
+#[derive(Clone)]
+pub enum LookupOutcome {
+    Resolved(ResolvedHost),
+    NxDomain,
+    Timeout,
+    Error(String),
+}
+
+impl LookupOutcome {
+    pub fn addresses(&self) -> &[Ipv4Addr] {
+        match self {
+            LookupOutcome::Resolved(host) => &host.addresses,
+            _ => &[],
+        }
+    }
+
+    // timeouts & errors are transient, worth another attempt
+    pub fn is_retryable(&self) -> bool {
+        matches!(*self, LookupOutcome::Timeout | LookupOutcome::Error(_))
+    }
+}
+
+impl Display for LookupOutcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LookupOutcome::Resolved(host) => write!(f, "resolved {}", host),
+            LookupOutcome::NxDomain => write!(f, "NXDOMAIN"),
+            LookupOutcome::Timeout => write!(f, "timed out"),
+            LookupOutcome::Error(reason) => write!(f, "error: {}", reason),
+        }
+    }
+}
+
+impl Display for ResolvedHost {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let addresses: Vec<String> = self.addresses.iter().map(|ip| ip.to_string()).collect();
+        match addresses.is_empty() {
+            true => write!(f, "no A records")?,
+            false => write!(f, "{}", addresses.join(", "))?,
+        }
+
+        if !self.cnames.is_empty() {
+            write!(f, " via CNAME {}", self.cnames.join(" -> "))?;
+        }
+        Ok(())
+    }
+}
+
+// Tally of lookup outcomes, so a "no match" result can be trusted (or not)
+#[derive(Default)]
+pub struct LookupSummary {
+    pub resolved: usize,
+    pub nxdomain: usize,
+    pub timeout: usize,
+    pub error: usize,
+}
+
+impl LookupSummary {
+    pub fn add(&mut self, outcome: &LookupOutcome) {
+        match outcome {
+            LookupOutcome::Resolved(_) => self.resolved += 1,
+            LookupOutcome::NxDomain => self.nxdomain += 1,
+            LookupOutcome::Timeout => self.timeout += 1,
+            LookupOutcome::Error(_) => self.error += 1,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.resolved + self.nxdomain + self.timeout + self.error
+    }
+
+    // every hostname got a definitive answer
+    pub fn is_complete(&self) -> bool {
+        self.timeout == 0 && self.error == 0
+    }
+}
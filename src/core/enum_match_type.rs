@@ -5,16 +5,30 @@
 use std::fmt;
 use std::fmt::{Display, Formatter};
 
+#[derive(Clone, Copy)]
 pub enum MatchType {
     Exact,
     Partial,
+    None,
+}
+
+impl MatchType {
+    // stable lowercase name, E.g. for --porcelain & CSV exports
+    pub fn label(&self) -> &'static str {
+        match *self {
+            MatchType::Exact => "exact",
+            MatchType::Partial => "partial",
+            MatchType::None => "none",
+        }
+    }
 }
 
 impl Display for MatchType {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match *self {
-            MatchType::Exact => write!(f, "{}", "  Exact  "),
-            MatchType::Partial => write!(f, "{}", " Partial "),
+            MatchType::Exact => write!(f, "  Exact  "),
+            MatchType::Partial => write!(f, " Partial "),
+            MatchType::None => write!(f, "  None   "),
         }
     }
 }
@@ -0,0 +1,145 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::enum_lookup_outcome::LookupOutcome;
+use super::worker_models::WorkerResponse;
+
+const CSV_HEADER: &str = "host,match,ip,outcome,addresses,cnames";
+
+// Save lookup results as CSV, one row per hostname:
+//   host,match,ip,outcome,addresses,cnames
+//   au42.nordvpn.com,exact,1.2.3.4,resolved,1.2.3.4 5.6.7.8,lb.nordvpn.com
+// - returns how many rows were written
+pub fn write_csv(path: &str, responses: &[WorkerResponse]) -> Result<usize, String> {
+    let mut contents = format!("{}\n", CSV_HEADER);
+    for item in responses.iter() {
+        contents.push_str(&csv_row(item));
+        contents.push('\n');
+    }
+
+    std::fs::write(path, contents).map_err(|err| format!("Unable to write {}: {}", path, err))?;
+    Ok(responses.len())
+}
+
+// Util: one CSV row of a lookup result
+fn csv_row(item: &WorkerResponse) -> String {
+    let ip = item.ip.map(|ip| ip.to_string()).unwrap_or_default();
+    let outcome = match &item.outcome {
+        LookupOutcome::Resolved(_) => String::from("resolved"),
+        other => other.to_string(),
+    };
+    let addresses: Vec<String> = item
+        .outcome
+        .addresses()
+        .iter()
+        .map(|ip| ip.to_string())
+        .collect();
+    let cnames = match &item.outcome {
+        LookupOutcome::Resolved(host) => host.cnames.join(" "),
+        _ => String::new(),
+    };
+
+    [
+        item.host.as_str(),
+        item.match_type.label(),
+        &ip,
+        &outcome,
+        &addresses.join(" "),
+        &cnames,
+    ]
+    .iter()
+    .map(|field| csv_field(field))
+    .collect::<Vec<String>>()
+    .join(",")
+}
+
+// Util: quote a field holding a comma, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Util: a JSON string literal
+pub(super) fn json_string(value: &str) -> String {
+    let mut json = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod csv_row_tests {
+    use super::*;
+    use crate::core::enum_country_code::CountryCode;
+    use crate::core::enum_lookup_outcome::ResolvedHost;
+    use crate::core::enum_match_type::MatchType;
+    use std::net::Ipv4Addr;
+
+    fn test_response(match_type: MatchType, outcome: LookupOutcome) -> WorkerResponse {
+        WorkerResponse {
+            country_code: CountryCode::Au,
+            num: 42,
+            match_type,
+            host: String::from("au42.nordvpn.com"),
+            ip: outcome.addresses().first().cloned(),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn when_resolved_should_list_addresses_and_cnames() {
+        let test_response = test_response(
+            MatchType::Exact,
+            LookupOutcome::Resolved(ResolvedHost {
+                addresses: vec![Ipv4Addr::new(1, 2, 3, 4), Ipv4Addr::new(5, 6, 7, 8)],
+                cnames: vec![String::from("lb.nordvpn.com")],
+            }),
+        );
+
+        assert_eq!(
+            csv_row(&test_response),
+            "au42.nordvpn.com,exact,1.2.3.4,resolved,1.2.3.4 5.6.7.8,lb.nordvpn.com"
+        );
+    }
+
+    #[test]
+    fn when_error_has_comma_should_quote_it() {
+        let test_response = test_response(
+            MatchType::None,
+            LookupOutcome::Error(String::from("refused, \"try later\"")),
+        );
+
+        assert_eq!(
+            csv_row(&test_response),
+            "au42.nordvpn.com,none,,\"error: refused, \"\"try later\"\"\",,"
+        );
+    }
+}
+
+#[cfg(test)]
+mod json_string_tests {
+    use super::*;
+
+    #[test]
+    fn when_quotes_and_control_chars_should_escape_them() {
+        assert_eq!(json_string("say \"hi\"\n\\"), "\"say \\\"hi\\\"\\n\\\\\"");
+        assert_eq!(json_string("\u{1}"), "\"\\u0001\"");
+    }
+}
@@ -0,0 +1,261 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::enum_country_code::CountryCode;
+use super::enum_lookup_outcome::LookupOutcome;
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// A hostname resolved by a previous search
+pub struct CachedHost {
+    pub country_code: CountryCode,
+    pub num: u32,
+    pub addresses: Vec<Ipv4Addr>,
+    pub seen: u64,    // unix time of the last lookup
+    pub changed: u64, // unix time the addresses last changed, 0 if they never have
+}
+
+// Hostnames resolved by previous searches, one tab separated line per hostname:
+//   au    42    1.2.3.4,5.6.7.8    1760000000    1750000000
+// - NXDOMAIN removes a hostname, timeouts & errors leave it untouched
+pub struct HostCache {
+    path: String,
+    hosts: HashMap<String, CachedHost>,
+}
+
+impl HostCache {
+    // $XDG_CACHE_HOME/fns/hosts.tsv, or ~/.cache/fns/hosts.tsv
+    pub fn default_path() -> Option<String> {
+        let cache_dir = match std::env::var("XDG_CACHE_HOME") {
+            Ok(dir) if !dir.is_empty() => dir,
+            _ => format!("{}/.cache", std::env::var("HOME").ok()?),
+        };
+        Some(format!("{}/fns/hosts.tsv", cache_dir))
+    }
+
+    // A missing file is an empty cache
+    pub fn load(path: &str) -> Result<HostCache, String> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(format!("Unable to read cache file {}: {}", path, err)),
+        };
+
+        let hosts = contents
+            .lines()
+            .filter_map(CachedHost::parse)
+            .map(|host| (host.hostname(), host))
+            .collect();
+
+        Ok(HostCache {
+            path: path.to_string(),
+            hosts,
+        })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        if let Some(dir) = Path::new(&self.path).parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|err| format!("Unable to create cache directory: {}", err))?;
+        }
+
+        let mut lines: Vec<String> = self.hosts.values().map(CachedHost::format).collect();
+        lines.sort();
+
+        // write then rename, so a concurrent search never reads half a cache
+        let temp_path = format!("{}.{}.tmp", &self.path, std::process::id());
+        std::fs::write(&temp_path, lines.join("\n") + "\n")
+            .and_then(|_| std::fs::rename(&temp_path, &self.path))
+            .map_err(|err| format!("Unable to write cache file {}: {}", &self.path, err))
+    }
+
+    pub fn update(
+        &mut self,
+        country_code: &CountryCode,
+        num: u32,
+        outcome: &LookupOutcome,
+        now: u64,
+    ) {
+        let hostname = format!("{}{}", country_code, num);
+
+        match outcome {
+            LookupOutcome::Resolved(host) => {
+                let mut addresses = host.addresses.clone();
+                addresses.sort();
+
+                let changed = match self.hosts.get(&hostname) {
+                    Some(cached) if cached.addresses != addresses => now,
+                    Some(cached) => cached.changed,
+                    None => 0,
+                };
+                self.hosts.insert(
+                    hostname,
+                    CachedHost {
+                        country_code: country_code.clone(),
+                        num,
+                        addresses,
+                        seen: now,
+                        changed,
+                    },
+                );
+            }
+            LookupOutcome::NxDomain => {
+                self.hosts.remove(&hostname);
+            }
+            LookupOutcome::Timeout | LookupOutcome::Error(_) => {}
+        }
+    }
+
+    pub fn hosts(&self) -> impl Iterator<Item = &CachedHost> {
+        self.hosts.values()
+    }
+}
+
+impl CachedHost {
+    // key of the cache, E.g. au42
+    fn hostname(&self) -> String {
+        format!("{}{}", self.country_code, self.num)
+    }
+
+    // None for malformed lines
+    fn parse(line: &str) -> Option<CachedHost> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 5 {
+            return None;
+        }
+
+        let addresses = fields[2]
+            .split(',')
+            .filter(|item| !item.is_empty())
+            .map(|item| item.parse::<Ipv4Addr>().ok())
+            .collect::<Option<Vec<Ipv4Addr>>>()?;
+
+        Some(CachedHost {
+            country_code: fields[0].parse::<CountryCode>().ok()?,
+            num: fields[1].parse::<u32>().ok()?,
+            addresses,
+            seen: fields[3].parse::<u64>().ok()?,
+            changed: fields[4].parse::<u64>().ok()?,
+        })
+    }
+
+    fn format(&self) -> String {
+        let addresses: Vec<String> = self.addresses.iter().map(|ip| ip.to_string()).collect();
+        format!(
+            "{}\t{}\t{}\t{}\t{}",
+            self.country_code,
+            self.num,
+            addresses.join(","),
+            self.seen,
+            self.changed
+        )
+    }
+}
+
+// Util: seconds since the unix epoch
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod host_cache_tests {
+    use super::*;
+    use crate::core::enum_lookup_outcome::ResolvedHost;
+
+    fn resolved(ip: Ipv4Addr) -> LookupOutcome {
+        LookupOutcome::Resolved(ResolvedHost {
+            addresses: vec![ip],
+            cnames: Vec::new(),
+        })
+    }
+
+    fn empty_cache() -> HostCache {
+        HostCache {
+            path: String::new(),
+            hosts: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn when_line_formatted_should_round_trip() {
+        let mut test_cache = empty_cache();
+        test_cache.update(
+            &CountryCode::Au,
+            42,
+            &resolved(Ipv4Addr::new(1, 2, 3, 4)),
+            100,
+        );
+
+        let test_line = test_cache.hosts().next().unwrap().format();
+        let actual_result = CachedHost::parse(&test_line).unwrap();
+
+        assert_eq!(actual_result.hostname(), "au42");
+        assert_eq!(actual_result.addresses, vec![Ipv4Addr::new(1, 2, 3, 4)]);
+        assert_eq!(actual_result.seen, 100);
+    }
+
+    #[test]
+    fn when_addresses_unchanged_should_keep_changed_time() {
+        let mut test_cache = empty_cache();
+        test_cache.update(
+            &CountryCode::Au,
+            1,
+            &resolved(Ipv4Addr::new(1, 2, 3, 4)),
+            100,
+        );
+        test_cache.update(
+            &CountryCode::Au,
+            1,
+            &resolved(Ipv4Addr::new(1, 2, 3, 4)),
+            200,
+        );
+
+        let actual_result = test_cache.hosts().next().unwrap();
+        assert_eq!(actual_result.seen, 200);
+        assert_eq!(actual_result.changed, 0);
+    }
+
+    #[test]
+    fn when_addresses_changed_should_update_changed_time() {
+        let mut test_cache = empty_cache();
+        test_cache.update(
+            &CountryCode::Au,
+            1,
+            &resolved(Ipv4Addr::new(1, 2, 3, 4)),
+            100,
+        );
+        test_cache.update(
+            &CountryCode::Au,
+            1,
+            &resolved(Ipv4Addr::new(1, 2, 3, 5)),
+            200,
+        );
+
+        assert_eq!(test_cache.hosts().next().unwrap().changed, 200);
+    }
+
+    #[test]
+    fn when_nxdomain_should_remove_host() {
+        let mut test_cache = empty_cache();
+        test_cache.update(
+            &CountryCode::Au,
+            1,
+            &resolved(Ipv4Addr::new(1, 2, 3, 4)),
+            100,
+        );
+        test_cache.update(&CountryCode::Au, 1, &LookupOutcome::Timeout, 150);
+        assert_eq!(test_cache.hosts().count(), 1);
+
+        test_cache.update(&CountryCode::Au, 1, &LookupOutcome::NxDomain, 200);
+        assert_eq!(test_cache.hosts().count(), 0);
+    }
+}
@@ -0,0 +1,147 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::worker_models::ScanControl;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct InterruptState {
+    count: u64,                    // times triggered so far
+    scans: Vec<Weak<ScanControl>>, // running scans built with .interrupt(..)
+    listeners: usize,              // live InterruptListeners
+}
+
+// A stop request (E.g. Ctrl-C) shared by the caller and the scans it runs, the library never
+// installs a signal handler itself:
+//   let interrupt = Interrupt::default();
+//   let handler = interrupt.clone();
+//   ctrlc::set_handler(move || { handler.trigger(); })?;
+//   let results = Scan::builder()...interrupt(&interrupt).build()?.run()?;
+#[derive(Clone, Default)]
+pub struct Interrupt {
+    shared: Arc<(Mutex<InterruptState>, Condvar)>,
+}
+
+// Wakes up on every trigger after it was created, E.g. to stop waiting between two sweeps
+pub struct InterruptListener {
+    interrupt: Interrupt,
+    seen: u64,
+}
+
+impl Interrupt {
+    // Stop every running scan & wake every listener
+    // - false when nobody was left to stop, so the caller can quit instead
+    pub fn trigger(&self) -> bool {
+        let (state, changed) = &*self.shared;
+        let mut state = state.lock().unwrap();
+        state.count += 1;
+
+        let scans: Vec<Arc<ScanControl>> = state.scans.iter().filter_map(Weak::upgrade).collect();
+        let stopped = scans.iter().any(|control| !control.is_interrupted());
+        for control in scans.iter() {
+            control.interrupt();
+        }
+        changed.notify_all();
+
+        stopped || state.listeners > 0
+    }
+
+    pub fn listen(&self) -> InterruptListener {
+        let (state, _) = &*self.shared;
+        let mut state = state.lock().unwrap();
+        state.listeners += 1;
+        InterruptListener {
+            interrupt: self.clone(),
+            seen: state.count,
+        }
+    }
+
+    // A scan that is stopped by the next trigger, until it finishes
+    pub(super) fn register(&self, control: &Arc<ScanControl>) {
+        let (state, _) = &*self.shared;
+        let mut state = state.lock().unwrap();
+        state.scans.retain(|item| item.strong_count() > 0);
+        state.scans.push(Arc::downgrade(control));
+    }
+}
+
+impl InterruptListener {
+    // Triggered since the last time it was asked
+    pub fn is_triggered(&mut self) -> bool {
+        self.wait_timeout(Duration::from_secs(0))
+    }
+
+    // Block until triggered or the timeout expires, true when triggered
+    pub fn wait_timeout(&mut self, timeout: Duration) -> bool {
+        let (state, changed) = &*self.interrupt.shared;
+        let deadline = Instant::now() + timeout;
+        let mut state = state.lock().unwrap();
+        loop {
+            if state.count != self.seen {
+                self.seen = state.count;
+                return true;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) {
+                return false;
+            }
+            state = changed.wait_timeout(state, remaining).unwrap().0;
+        }
+    }
+}
+
+impl Drop for InterruptListener {
+    fn drop(&mut self) {
+        let (state, _) = &*self.interrupt.shared;
+        state.lock().unwrap().listeners -= 1;
+    }
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod interrupt_tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn when_nothing_running_should_not_be_handled() {
+        assert!(!Interrupt::default().trigger());
+    }
+
+    #[test]
+    fn when_scan_registered_should_interrupt_it_once() {
+        let test_interrupt = Interrupt::default();
+        let test_control = Arc::new(ScanControl::default());
+        test_interrupt.register(&test_control);
+
+        assert!(test_interrupt.trigger());
+        assert!(test_control.is_interrupted());
+        // a second trigger has nothing left to stop
+        assert!(!test_interrupt.trigger());
+    }
+
+    #[test]
+    fn when_triggered_should_wake_listener() {
+        let test_interrupt = Interrupt::default();
+        let mut test_listener = test_interrupt.listen();
+        assert!(!test_listener.is_triggered());
+
+        let handler = test_interrupt.clone();
+        thread::spawn(move || handler.trigger());
+
+        assert!(test_listener.wait_timeout(Duration::from_secs(5)));
+        assert!(!test_listener.is_triggered());
+    }
+
+    #[test]
+    fn when_listener_dropped_should_not_be_handled() {
+        let test_interrupt = Interrupt::default();
+        drop(test_interrupt.listen());
+
+        assert!(!test_interrupt.trigger());
+    }
+}
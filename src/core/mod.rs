@@ -2,25 +2,86 @@
  *  Copyright (c) Spudmash Media Pty Ltd
  *  Licensed under the MIT License. See License.md in the project root for license information.
  *--------------------------------------------------------------------------------------------*/
+mod adaptive_concurrency;
+use adaptive_concurrency::AdaptiveConcurrency;
+
+mod bench;
+pub use bench::entry_point_bench;
+
+mod tui;
+pub use tui::entry_point_tui;
+
+mod shell;
+pub use shell::entry_point_shell;
+
+mod serve;
+pub use serve::entry_point_serve;
+
+mod daemon;
+pub use daemon::{daemon_socket_path, entry_point_daemon};
+
+mod watch;
+pub use watch::{entry_point_watch, parse_interval};
+
+mod export;
+pub use export::write_csv;
+
+mod auto_range;
+use auto_range::discover_range;
+
+mod host_cache;
+pub use host_cache::{CachedHost, HostCache};
+
+mod scan;
+pub use scan::{Scan, ScanBuilder, ScanEvent, ScanHandle, ScanResults};
+
+mod output;
+pub use output::Output;
+
+mod progress;
+pub use progress::{IndicatifProgress, LogProgress, MatchPrinter, NoProgress, ScanProgress};
+
+mod scan_order;
+use scan_order::prioritize;
+
+mod scheduling;
+use scheduling::shuffle;
+pub use scheduling::RateLimiter;
+
+mod interrupt;
+pub use interrupt::{Interrupt, InterruptListener};
+
+mod checkpoint;
+pub use checkpoint::Checkpoint;
+use checkpoint::HostRecord;
+
 pub mod enum_country_code;
 use enum_country_code::CountryCode;
 
+pub mod enum_exit_code;
+
+mod dns_resolver;
+pub use dns_resolver::DnsResolver;
+
+mod enum_lookup_outcome;
+pub use enum_lookup_outcome::{LookupOutcome, LookupSummary, ResolvedHost};
+
 mod enum_match_type;
-use enum_match_type::MatchType;
+pub use enum_match_type::MatchType;
 
 mod worker_models;
-use worker_models::{SearchFilter, WorkerRequest, WorkerResponse};
+pub use worker_models::{Concurrency, LookupPolicy, SearchFilter, WorkerResponse};
+use worker_models::{FleetEdge, ScanControl, WorkerContext, WorkerRequest};
 
-use indicatif::{HumanDuration, MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::HumanDuration;
+use std::any::Any;
+use std::collections::HashSet;
 use std::net::Ipv4Addr;
-use std::process::Command;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc};
 use std::thread;
 
-use num_cpus;
-
 // Info: CPU information to help optimize how many threads to spawn with -t option
 pub fn verbose_info(requested_thread_count: usize) {
     let available_cpus = &num_cpus::get_physical();
@@ -31,10 +92,8 @@ pub fn verbose_info(requested_thread_count: usize) {
     println!(" - {} cores available", &available_cpus);
     println!(" - {} threads available", &available_cpu_threads);
 
-    let thread_suggestion = &num_cpus::get() * 2;
     println!(
-        "\n💡 Optimize search speed by doubling the thread count or higher. E.g. -t {}\n",
-        &thread_suggestion
+        "\n💡 Measure the fastest thread count for your network with `fns bench`, or let --adaptive tune it\n"
     );
 
     println!(
@@ -45,24 +104,22 @@ pub fn verbose_info(requested_thread_count: usize) {
 
 // use by main in clap validator
 pub fn is_valid_ip(source_ip: String) -> Result<(), String> {
-    println!("got this: {:?}", &source_ip);
-    let test_ip = source_ip.parse::<Ipv4Addr>();
-
-    let mut excluded_ip_addresses: Vec<Ipv4Addr> = Vec::new();
-    excluded_ip_addresses.push("0.0.0.0".parse::<Ipv4Addr>().unwrap());
-    excluded_ip_addresses.push("127.0.0.1".parse::<Ipv4Addr>().unwrap());
-
-    if test_ip.is_err() {
-        Err(String::from("Invalid IP Address"))
-    } else if excluded_ip_addresses.contains(&test_ip.unwrap()) {
-        Err(String::from("IP Address is blacklisted"))
-    } else {
-        Ok(())
+    let excluded_ip_addresses: Vec<Ipv4Addr> = vec![
+        "0.0.0.0".parse::<Ipv4Addr>().unwrap(),
+        "127.0.0.1".parse::<Ipv4Addr>().unwrap(),
+    ];
+
+    match source_ip.parse::<Ipv4Addr>() {
+        Err(_) => Err(String::from("Invalid IP Address")),
+        Ok(test_ip) if excluded_ip_addresses.contains(&test_ip) => {
+            Err(String::from("IP Address is blacklisted"))
+        }
+        Ok(_) => Ok(()),
     }
 }
 
 // Util: build dataframe from start value to end value
-fn build_data(start: u32, end: u32) -> Vec<u32> {
+pub fn build_data(start: u32, end: u32) -> Vec<u32> {
     let mut vec = Vec::new();
     for n in start..=end {
         vec.push(n)
@@ -70,37 +127,130 @@ fn build_data(start: u32, end: u32) -> Vec<u32> {
     vec
 }
 
-// Util: build vpn name
-fn get_vpn_string(country: &CountryCode, num: &u32) -> String {
-    return format!("{}{}.nordvpn.com", country.to_string(), &num);
+// Util: build dataframe from a range list, E.g. "1-100,250,500-800"
+// - numbers are sorted & de-duplicated, whitespace and newlines work as separators too
+pub fn parse_range_list(range_list: &str) -> Result<Vec<u32>, String> {
+    let mut vec = Vec::new();
+
+    for item in range_list
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|item| !item.is_empty())
+    {
+        let invalid = || format!("Invalid range: {}", item);
+
+        match item.split_once('-') {
+            Some((start, end)) => {
+                let start = start.parse::<u32>().map_err(|_| invalid())?;
+                let end = end.parse::<u32>().map_err(|_| invalid())?;
+                if start > end {
+                    return Err(invalid());
+                }
+                vec.extend(build_data(start, end));
+            }
+            None => vec.push(item.parse::<u32>().map_err(|_| invalid())?),
+        }
+    }
+
+    vec.sort_unstable();
+    vec.dedup();
+    Ok(vec)
+}
+
+// Util: the shortest range list of some numbers, E.g. [1, 2, 3, 250] -> "1-3,250"
+fn format_range_list(numbers: &[u32]) -> String {
+    let mut numbers = numbers.to_vec();
+    numbers.sort_unstable();
+    numbers.dedup();
+
+    let mut ranges: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < numbers.len() {
+        let start = numbers[i];
+        while i + 1 < numbers.len() && numbers[i + 1] == numbers[i] + 1 {
+            i += 1;
+        }
+        match numbers[i] {
+            end if end == start => ranges.push(start.to_string()),
+            end => ranges.push(format!("{}-{}", start, end)),
+        }
+        i += 1;
+    }
+    ranges.join(",")
 }
 
-// Parse ping output string
-fn parse_ping_result(ping_data: String) -> Ipv4Addr {
-    let data: Vec<&str> = ping_data.split('\n').collect();
+// Util: read a file of server numbers, one number or range per line, # for comments
+pub fn read_range_file(path: &str) -> Result<Vec<u32>, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("Unable to read range file {}: {}", path, err))?;
+
+    let range_list: Vec<&str> = contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .collect();
 
-    let target = data[0];
-    let mut output = "0.0.0.0";
+    parse_range_list(&range_list.join(","))
+}
 
-    if target.len() > 0 {
-        let start_idx = target.find("(").unwrap() + 1;
-        let end_idx = target.find(")").unwrap();
-        output = &target[start_idx..end_idx];
+// Util: a country code to scan, E.g. "de"
+fn parse_country(value: &str) -> Result<CountryCode, String> {
+    match value.parse::<CountryCode>() {
+        Ok(CountryCode::Empty) | Err(_) => Err(format!("Unknown country code: {}", value)),
+        Ok(country_code) => Ok(country_code),
     }
+}
 
-    output.parse::<Ipv4Addr>().unwrap()
+// Util: country codes from a list, E.g. "au,de" or "all"
+pub fn parse_country_list(value: &str) -> Result<Vec<CountryCode>, String> {
+    let mut countries = Vec::new();
+    for item in value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        if item.eq_ignore_ascii_case("all") {
+            countries.extend(CountryCode::all());
+        } else {
+            countries.push(parse_country(item)?);
+        }
+    }
+    if countries.is_empty() {
+        return Err(String::from("At least one Country Code is required"));
+    }
+    Ok(countries)
 }
 
-// Util: ping host name, grep ip address
-fn ping_by_hostname(hostname: &String) -> Ipv4Addr {
-    let mut ping = Command::new("ping");
-    ping.arg("-c 1").arg(hostname);
+// Util: build vpn name
+fn get_vpn_string(country: &CountryCode, num: &u32) -> String {
+    format!("{}{}.nordvpn.com", country, &num)
+}
 
-    let result = ping.output().expect("failed to execute process");
+// Util: resolve host name, retrying timeouts & errors with exponential backoff
+// - the outcome of the last attempt is returned once retries are exhausted, or the search is stopped
+fn lookup_hostname(
+    resolver: &DnsResolver,
+    hostname: &str,
+    policy: &LookupPolicy,
+    control: &ScanControl,
+) -> LookupOutcome {
+    let mut attempt = 0;
 
-    let ping_response = String::from_utf8(result.stdout).unwrap();
+    loop {
+        policy.throttle(control);
+        let outcome = resolver.lookup(hostname, policy.timeout);
+        if !outcome.is_retryable() || attempt >= policy.retries {
+            return outcome;
+        }
+        attempt += 1;
 
-    parse_ping_result(ping_response)
+        // back off in small steps so a stopped search isn't kept waiting
+        let backoff_until = Instant::now() + policy.backoff_delay(attempt);
+        while Instant::now() < backoff_until {
+            if control.is_stopped() {
+                return outcome;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
 }
 
 // Check if both ip's in same subnet,  i.e. 1.2.3.100 == 1.2.3.200
@@ -113,124 +263,430 @@ pub fn is_in_same_subnet(source: &Ipv4Addr, target: &Ipv4Addr) -> bool {
         && source_oct[2] == target_oct[2]
 }
 
-// check if same ip and not 0.0.0.0
+// check if same ip
 pub fn is_same_ip(source: &Ipv4Addr, target: &Ipv4Addr) -> bool {
-    let source_oct = source.octets();
-    let target_oct = target.octets();
+    source.octets() == target.octets()
+}
 
-    let test_empty_ip_address = Ipv4Addr::new(0, 0, 0, 0);
+// Classify a lookup outcome against the ips being searched for
+// - every A record is checked, an exact match on any record wins over a partial match
+fn get_match_type(outcome: &LookupOutcome, filter: &SearchFilter) -> (MatchType, Option<Ipv4Addr>) {
+    let addresses = outcome.addresses();
 
-    source.ne(&test_empty_ip_address) && source_oct == target_oct
+    let exact = addresses
+        .iter()
+        .find(|ip| filter.ips.iter().any(|target| is_same_ip(ip, target)));
+    if let Some(ip) = exact {
+        return (MatchType::Exact, Some(*ip));
+    }
+
+    let partial = addresses.iter().find(|ip| {
+        filter
+            .ips
+            .iter()
+            .any(|target| is_in_same_subnet(ip, target))
+    });
+    match partial {
+        Some(ip) => (MatchType::Partial, Some(*ip)),
+        None => (MatchType::None, None),
+    }
+}
+
+// Util: classify a lookup outcome into the response sent back to main thread
+fn build_response(
+    country_code: &CountryCode,
+    num: u32,
+    outcome: LookupOutcome,
+    filter: &SearchFilter,
+) -> WorkerResponse {
+    let (match_type, ip) = get_match_type(&outcome, filter);
+
+    WorkerResponse {
+        country_code: country_code.clone(),
+        num,
+        match_type,
+        host: get_vpn_string(country_code, &num),
+        ip,
+        outcome,
+    }
 }
 
 // worker task:
-// - iterate through dataframe and ping each hostname to find a match
-// - transmit match back to main thread
+// - iterate through dataframe and resolve each hostname to find a match
+// - transmit every lookup outcome back to main thread
+// - with --max-gap, hostnames past the edge of a country's fleet are dropped
 fn worker_task(
-    pb: ProgressBar,
+    worker: usize,
     tx: mpsc::Sender<WorkerResponse>,
-    filter: SearchFilter,
-    payload: WorkerRequest,
+    context: Arc<WorkerContext>,
+    payload: Vec<WorkerRequest>,
 ) {
-    // scan dataframe sequentially
-    for item in payload.data.into_iter() {
-        let hostname = get_vpn_string(&payload.country_code, &item);
-
-        pb.set_message(&format!("🔎 [scanning: {}]", &hostname));
-        let response = ping_by_hostname(&hostname);
-
-        // exact match
-        if is_same_ip(&response, &filter.ip) {
-            let result = WorkerResponse {
-                match_type: MatchType::Exact,
-                host: hostname,
-                ip: response,
-            };
-            tx.send(result).unwrap(); // notify main thread
-        } else if is_in_same_subnet(&response, &filter.ip) {
-            //partial match
-            let result = WorkerResponse {
-                match_type: MatchType::Partial,
-                host: hostname,
-                ip: response,
-            };
+    let control = &context.control;
+
+    // scan dataframe sequentially, one country after another
+    for request in payload.into_iter() {
+        let edge = context.fleet_edge(&request.country_code);
+
+        for item in request.data.into_iter() {
+            // another worker found everything we're looking for, drop the pending lookups
+            control.wait_while_paused();
+            if control.is_stopped() {
+                return;
+            }
+
+            let hostname = get_vpn_string(&request.country_code, &item);
+
+            // not a break, prioritized dataframes aren't in ascending order
+            if let Some(edge) = edge.filter(|edge| edge.is_past_edge(item)) {
+                edge.skip(1);
+                context.progress.host_scanned(worker, &hostname);
+                continue;
+            }
+
+            if let Some(adaptive) = &context.adaptive {
+                adaptive.acquire(control);
+            }
+            let started = Instant::now();
+            let outcome = lookup_hostname(&context.resolver, &hostname, &context.policy, control);
+            if let Some(adaptive) = &context.adaptive {
+                adaptive.release(started.elapsed(), &outcome);
+            }
+
+            if let Some(checkpoint) = &context.checkpoint {
+                checkpoint.record(&request.country_code, item, &outcome);
+            }
+            if let Some(edge) = edge {
+                edge.record(item, &outcome);
+            }
+
+            let result = build_response(&request.country_code, item, outcome, &context.filter);
+            if let (MatchType::Exact, Some(ip)) = (&result.match_type, result.ip) {
+                if control.record_exact(ip, &context.filter) && context.filter.first {
+                    control.stop();
+                }
+            }
+
             tx.send(result).unwrap(); // notify main thread
+            context.progress.host_scanned(worker, &hostname);
         }
-        pb.inc(1);
     }
+}
 
-    // Signal to main thread of completion
-    pb.finish_with_message("✨Done✨");
+// Util: the message a thread panicked with
+fn panic_message(err: &Box<dyn Any + Send>) -> String {
+    match err.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match err.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => String::from("unknown panic"),
+        },
+    }
+}
+
+// Info: outcome counts & abandoned hostnames, so we know whether a "no match" run was complete
+fn print_lookup_summary(
+    output: Output,
+    summary: &LookupSummary,
+    results: &[WorkerResponse],
+    policy: &LookupPolicy,
+) {
+    output.report(&format!("\nLookup Summary: {} hostnames", summary.total()));
+    output.report(&format!(" - {} resolved", summary.resolved));
+    output.report(&format!(" - {} NXDOMAIN", summary.nxdomain));
+    output.report(&format!(" - {} timed out", summary.timeout));
+    output.report(&format!(" - {} errors", summary.error));
+
+    if summary.is_complete() {
+        return;
+    }
+
+    output.report(&format!(
+        "\n⚠️  Abandoned Lookups (gave up after {} retries), results may be incomplete:\n",
+        policy.retries
+    ));
+    for item in results.iter().filter(|item| item.outcome.is_retryable()) {
+        output.report(&format!(" - {}: {}", &item.host, &item.outcome));
+    }
+}
+
+// Info: where each country's sweep ended with --max-gap
+fn print_fleet_edges(output: Output, edges: &[FleetEdge], max_gap: Option<u32>) {
+    for edge in edges.iter().filter(|edge| edge.skipped() > 0) {
+        let last_live = match edge.highest_live() {
+            Some(num) => get_vpn_string(&edge.country_code, &num),
+            None => String::from("no live hosts"),
+        };
+        output.report(&format!(
+            "\n🏁 {}: fleet ends at {}, {} hostnames past it skipped (--max-gap {})",
+            &edge.country_code,
+            last_live,
+            edge.skipped(),
+            max_gap.unwrap_or_default()
+        ));
+    }
 }
 
 // --Entry Point--
-pub fn entry_point_mt(
-    country_code: CountryCode,
-    match_ip: Ipv4Addr,
-    start_num: u32,
-    end_num: u32,
-    thread_count: usize,
-) -> Result<(), ()> {
-    let stop_watch = Instant::now();
+// - with an events channel every lookup is also sent as a ScanEvent
+fn entry_point_mt(
+    scan: Scan,
+    control: Arc<ScanControl>,
+    events: Option<mpsc::Sender<ScanEvent>>,
+) -> Result<ScanResults, String> {
+    let Scan {
+        requests,
+        filter,
+        collect_table,
+        resolver,
+        concurrency,
+        policy,
+        mut checkpoint,
+        max_gap,
+        output,
+        progress,
+        ..
+    } = scan;
+    let output = output.unwrap_or(Output::Silent);
+
+    if requests.is_empty() {
+        return Err(String::from("At least one Country Code is required"));
+    }
+    if filter.ips.is_empty() && !collect_table {
+        return Err(String::from("At least one Ip4 Address is required"));
+    }
+    let thread_count = concurrency.threads();
+    if thread_count == 0 {
+        return Err(String::from("Thread Count must be at least 1"));
+    }
+    if policy.timeout.is_zero() {
+        return Err(String::from("Timeout must be greater than zero"));
+    }
+    if requests.iter().all(|request| request.data.is_empty()) {
+        return Err(String::from("No server numbers to scan"));
+    }
+    if max_gap == Some(0) {
+        return Err(String::from("Max Gap must be at least 1"));
+    }
 
-    // prepare progress bar
-    let m = MultiProgress::new();
-    let sty = ProgressStyle::default_bar()
-        .template("[{elapsed_precise}]  {bar:13} {spinner} {msg}")
-        .progress_chars("◼︎□■.");
+    let stop_watch = Instant::now();
 
     // prepare mpsc channel
     let (tx, rx) = mpsc::channel();
 
+    // lookups completed by a previous run are replayed first, then skipped
+    let requested: HashSet<String> = requests
+        .iter()
+        .flat_map(|request| {
+            request
+                .data
+                .iter()
+                .map(move |num| get_vpn_string(&request.country_code, num))
+        })
+        .collect();
+    let resumed: Vec<HostRecord> = checkpoint
+        .as_mut()
+        .map(|checkpoint| checkpoint.take_completed())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|record| requested.contains(&get_vpn_string(&record.country_code, &record.num)))
+        .collect();
+
+    let edges: Vec<FleetEdge> = match max_gap {
+        Some(max_gap) => requests
+            .iter()
+            .map(|request| {
+                FleetEdge::new(request.country_code.clone(), request.data.clone(), max_gap)
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let context = Arc::new(WorkerContext {
+        filter,
+        resolver,
+        policy,
+        control,
+        checkpoint,
+        edges,
+        progress: progress.unwrap_or_else(|| output.progress()),
+        adaptive: match concurrency {
+            Concurrency::Adaptive(max) => Some(AdaptiveConcurrency::new(max)),
+            Concurrency::Fixed(_) => None,
+        },
+    });
+
+    let mut completed: HashSet<String> = HashSet::new();
+    for record in resumed.into_iter() {
+        if let Some(edge) = context.fleet_edge(&record.country_code) {
+            edge.record(record.num, &record.outcome);
+        }
+        let result = build_response(
+            &record.country_code,
+            record.num,
+            record.outcome,
+            &context.filter,
+        );
+        if let (MatchType::Exact, Some(ip)) = (&result.match_type, result.ip) {
+            if context.control.record_exact(ip, &context.filter) && context.filter.first {
+                context.control.stop();
+            }
+        }
+        completed.insert(result.host.clone());
+        tx.send(result).unwrap();
+    }
+    if !completed.is_empty() {
+        output.report(&format!(
+            "♻️  Resuming search, {} hostnames already scanned\n",
+            completed.len()
+        ));
+    }
+
     // prepare data
-    let vec = build_data(start_num, end_num);
+    let hostname_count: usize = requests.iter().map(|request| request.data.len()).sum();
+
+    // deal dataframe: every worker takes every Nth hostname of each country, so together they sweep upwards
+    let mut chunk_dataframe: Vec<Vec<WorkerRequest>> =
+        (0..thread_count).map(|_| Vec::new()).collect();
+    for request in requests.iter() {
+        let country_code = &request.country_code;
+        let pending: Vec<u32> = request
+            .data
+            .iter()
+            .filter(|num| !completed.contains(&get_vpn_string(country_code, num)))
+            .cloned()
+            .collect();
+        if pending.is_empty() {
+            continue;
+        }
 
-    // chunk dataframe
-    let chunk_size = &vec.len() / thread_count; // as even as possible
-    let chunk_dataframe: Vec<WorkerRequest> = vec
-        .chunks(chunk_size)
+        let mut shares: Vec<Vec<u32>> = (0..thread_count).map(|_| Vec::new()).collect();
+        for (idx, item) in pending.into_iter().enumerate() {
+            shares[idx % thread_count].push(item);
+        }
+        for (idx, share) in shares.into_iter().enumerate() {
+            if !share.is_empty() {
+                chunk_dataframe[idx].push(WorkerRequest::new(country_code.clone(), share));
+            }
+        }
+    }
+
+    output.report("\nSearch Results:\n");
+    let chunk_dataframe: Vec<Vec<WorkerRequest>> = chunk_dataframe
         .into_iter()
-        .map(|item| WorkerRequest::new(country_code.clone(), item.to_vec()))
+        .filter(|item| !item.is_empty())
         .collect();
+    let worker_loads: Vec<usize> = chunk_dataframe
+        .iter()
+        .map(|item| item.iter().map(|request| request.data.len()).sum())
+        .collect();
+    context.progress.started(&worker_loads);
 
     // spawn threads
-    for item in chunk_dataframe.into_iter() {
-        let pb = m.add(ProgressBar::new(item.data.len() as u64));
-        pb.set_style(sty.clone());
-
+    let mut handles: Vec<thread::JoinHandle<()>> = Vec::new();
+    for (worker, item) in chunk_dataframe.into_iter().enumerate() {
         let tx1 = mpsc::Sender::clone(&tx);
+        let context1 = Arc::clone(&context);
 
-        let search_param = SearchFilter {
-            ip: match_ip.clone(),
-        };
-
-        let _ = thread::spawn(move || {
-            worker_task(pb, tx1, search_param, item);
-        });
+        handles.push(thread::spawn(move || {
+            worker_task(worker, tx1, context1, item);
+        }));
     }
 
-    // join progress bars on all threads
-    m.join_and_clear().unwrap();
+    // only the workers hold a sender now, so the channel closes exactly when the last one is done
+    drop(tx);
 
-    // messags received via channel
-    println!("\nSearch Results:\n");
-    loop {
-        match &rx.recv_timeout(std::time::Duration::new(1, 0)) {
-            Ok(data) => {
-                println!("[{}]\t[ {}, {}]", &data.match_type, &data.host, &data.ip);
-            }
-            Err(_err) => {
-                println!(
-                    "\nElapsed Time: {} ({}ms)\n",
-                    HumanDuration(stop_watch.elapsed()),
-                    stop_watch.elapsed().as_millis()
-                );
-                break;
+    // messages received via channel
+    let mut results: Vec<WorkerResponse> = Vec::new();
+    let mut summary = LookupSummary::default();
+    for data in rx.iter() {
+        summary.add(&data.outcome);
+        if data.ip.is_some() {
+            context.progress.matched(&data);
+        }
+        // the caller went away, nobody is listening for the rest
+        if let Some(events) = &events {
+            if events.send(ScanEvent::from_response(data.clone())).is_err() {
+                context.control.stop();
             }
         }
+        results.push(data);
     }
+    context.progress.finished(&summary);
 
-    Ok(())
+    // a worker that panicked dropped the rest of its dataframe
+    let panics: Vec<String> = handles
+        .into_iter()
+        .filter_map(|handle| handle.join().err())
+        .map(|err| panic_message(&err))
+        .collect();
+
+    print_scan_report(
+        output,
+        &context,
+        &summary,
+        &results,
+        max_gap,
+        hostname_count,
+        stop_watch,
+    );
+
+    if !panics.is_empty() {
+        return Err(format!(
+            "{} worker thread(s) panicked, results are incomplete: {}",
+            panics.len(),
+            panics.join("; ")
+        ));
+    }
+
+    Ok(ScanResults {
+        responses: results,
+        summary,
+        interrupted: context.control.is_interrupted(),
+        cache_error: None,
+    })
+}
+
+// Info: outcome summary, fleet edges, concurrency and why the search ended
+fn print_scan_report(
+    output: Output,
+    context: &WorkerContext,
+    summary: &LookupSummary,
+    results: &[WorkerResponse],
+    max_gap: Option<u32>,
+    hostname_count: usize,
+    stop_watch: Instant,
+) {
+    print_lookup_summary(output, summary, results, &context.policy);
+    print_fleet_edges(output, &context.edges, max_gap);
+    if let Some(adaptive) = &context.adaptive {
+        output.report(&format!(
+            "\n⚙️  Adaptive concurrency settled at {} lookups in flight (peak {}, max {})",
+            adaptive.limit(),
+            adaptive.peak(),
+            adaptive.max()
+        ));
+    }
+
+    if context.control.is_interrupted() {
+        output.report(&format!(
+            "\n⏹  Interrupted, partial results: {} of {} hostnames scanned ({}%)",
+            summary.total(),
+            hostname_count,
+            summary.total() * 100 / hostname_count
+        ));
+    } else if context.control.is_stopped() {
+        output.report(&format!(
+            "\n⏹  Stopped early, every IP matched exactly: {} of {} hostnames scanned (use --all to scan everything)",
+            summary.total(),
+            hostname_count
+        ));
+    }
+
+    output.report(&format!(
+        "\nElapsed Time: {} ({}ms)\n",
+        HumanDuration(stop_watch.elapsed()),
+        stop_watch.elapsed().as_millis()
+    ));
 }
 
 //-----------------
@@ -279,30 +735,135 @@ mod build_data_tests {
 }
 
 #[cfg(test)]
-mod parse_ping_result_tests {
+mod lookup_policy_tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn backoff_delay_should_double_each_attempt() {
+        let test_policy = LookupPolicy::new(Duration::from_secs(5), 3);
+
+        assert_eq!(test_policy.backoff_delay(1), Duration::from_millis(500));
+        assert_eq!(test_policy.backoff_delay(2), Duration::from_millis(1000));
+        assert_eq!(test_policy.backoff_delay(3), Duration::from_millis(2000));
+    }
+}
+
+#[cfg(test)]
+mod scan_control_tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn when_single_ip_found_should_return_true() {
+        let test_filter = SearchFilter::new(vec![Ipv4Addr::new(1, 2, 3, 4)]);
+        let test_control = ScanControl::default();
+
+        assert!(test_filter.first);
+        assert!(test_control.record_exact(Ipv4Addr::new(1, 2, 3, 4), &test_filter));
+    }
+
+    #[test]
+    fn when_some_ips_still_missing_should_return_false() {
+        let test_filter =
+            SearchFilter::new(vec![Ipv4Addr::new(1, 2, 3, 4), Ipv4Addr::new(5, 6, 7, 8)]);
+        let test_control = ScanControl::default();
+
+        assert!(!test_filter.first);
+        assert!(!test_control.record_exact(Ipv4Addr::new(1, 2, 3, 4), &test_filter));
+        assert!(test_control.record_exact(Ipv4Addr::new(5, 6, 7, 8), &test_filter));
+    }
+
+    #[test]
+    fn when_paused_and_stopped_should_stop_waiting() {
+        let test_control = Arc::new(ScanControl::default());
+        test_control.pause();
+
+        let waiting_control = Arc::clone(&test_control);
+        let test_handle = thread::spawn(move || waiting_control.wait_while_paused());
+        test_control.stop();
+
+        assert!(test_handle.join().is_ok());
+        assert!(test_control.is_paused());
+    }
+}
+
+#[cfg(test)]
+mod panic_message_tests {
+    use super::*;
+
+    #[test]
+    fn when_worker_panics_should_return_its_message() {
+        let test_handle = thread::spawn(|| panic!("lookup failed for {}", "au42.nordvpn.com"));
+        let test_err = test_handle.join().unwrap_err();
+
+        assert_eq!(
+            panic_message(&test_err),
+            "lookup failed for au42.nordvpn.com"
+        );
+    }
+}
+
+#[cfg(test)]
+mod fleet_edge_tests {
     use super::*;
+    use enum_lookup_outcome::ResolvedHost;
     use std::net::Ipv4Addr;
 
+    fn resolved() -> LookupOutcome {
+        LookupOutcome::Resolved(ResolvedHost {
+            addresses: vec![Ipv4Addr::new(1, 2, 3, 4)],
+            cnames: Vec::new(),
+        })
+    }
+
     #[test]
-    fn when_valid_hostname_should_return_ip() {
-        let test_ping_result = "PING au548.nordvpn.com (41.42.43.44): 56 data bytes".to_string();
+    fn when_gap_after_highest_live_is_all_nxdomain_should_be_past_edge() {
+        let test_edge = FleetEdge::new(CountryCode::Au, build_data(1, 20), 3);
+        test_edge.record(5, &resolved());
+        test_edge.record(6, &LookupOutcome::NxDomain);
+        test_edge.record(7, &LookupOutcome::NxDomain);
 
-        let expect_ip_string = "41.42.43.44".to_string().parse::<Ipv4Addr>().unwrap();
+        assert!(!test_edge.is_past_edge(9));
 
-        let actual_result = parse_ping_result(test_ping_result);
+        test_edge.record(8, &LookupOutcome::NxDomain);
 
-        assert_eq!(expect_ip_string, actual_result);
+        assert!(test_edge.is_past_edge(9));
+        assert!(!test_edge.is_past_edge(8));
+        assert_eq!(test_edge.highest_live(), Some(5));
     }
 
     #[test]
-    fn when_invalid_hostname_empty_should_return_zero_oct() {
-        let test_ping_result = "".to_string();
+    fn when_live_host_found_past_gap_should_reopen_edge() {
+        let test_edge = FleetEdge::new(CountryCode::Au, build_data(1, 20), 2);
+        test_edge.record(1, &LookupOutcome::NxDomain);
+        test_edge.record(2, &LookupOutcome::NxDomain);
+        assert!(test_edge.is_past_edge(3));
+
+        test_edge.record(3, &resolved());
 
-        let expect_ip_string = "0.0.0.0".to_string().parse::<Ipv4Addr>().unwrap();
+        assert!(!test_edge.is_past_edge(4));
+        assert_eq!(test_edge.highest_live(), Some(3));
+    }
 
-        let actual_result = parse_ping_result(test_ping_result);
+    #[test]
+    fn when_timeout_in_gap_should_not_be_past_edge() {
+        let test_edge = FleetEdge::new(CountryCode::Au, build_data(1, 20), 2);
+        test_edge.record(1, &resolved());
+        test_edge.record(2, &LookupOutcome::Timeout);
+        test_edge.record(3, &LookupOutcome::NxDomain);
 
-        assert_eq!(expect_ip_string, actual_result);
+        assert!(!test_edge.is_past_edge(4));
+    }
+
+    #[test]
+    fn when_sparse_numbers_should_count_gap_in_positions() {
+        let test_edge = FleetEdge::new(CountryCode::Au, vec![10, 20, 30, 40], 2);
+        test_edge.record(10, &resolved());
+        test_edge.record(20, &LookupOutcome::NxDomain);
+        test_edge.record(30, &LookupOutcome::NxDomain);
+
+        assert!(test_edge.is_past_edge(40));
     }
 }
 
@@ -317,11 +878,11 @@ mod is_in_same_subnet_tests {
         let test_b: u8 = 20;
         let test_c: u8 = 30;
 
-        let test_source = Ipv4Addr::new(test_a.clone(), test_b.clone(), test_c.clone(), 1);
-        let test_target = Ipv4Addr::new(test_a.clone(), test_b.clone(), test_c.clone(), 42);
+        let test_source = Ipv4Addr::new(test_a, test_b, test_c, 1);
+        let test_target = Ipv4Addr::new(test_a, test_b, test_c, 42);
 
         let actual_result = is_in_same_subnet(&test_source, &test_target);
-        assert_eq!(actual_result, true);
+        assert!(actual_result);
     }
 
     #[test]
@@ -329,11 +890,11 @@ mod is_in_same_subnet_tests {
         let test_a: u8 = 10;
         let test_b: u8 = 20;
 
-        let test_source = Ipv4Addr::new(test_a.clone(), test_b.clone(), 0, 1);
-        let test_target = Ipv4Addr::new(test_a.clone(), test_b.clone(), 1, 42);
+        let test_source = Ipv4Addr::new(test_a, test_b, 0, 1);
+        let test_target = Ipv4Addr::new(test_a, test_b, 1, 42);
 
         let actual_result = is_in_same_subnet(&test_source, &test_target);
-        assert_eq!(actual_result, false);
+        assert!(!actual_result);
     }
 
     #[test]
@@ -341,11 +902,11 @@ mod is_in_same_subnet_tests {
         let test_a: u8 = 10;
         let test_b: u8 = 20;
         let test_c: u8 = 30;
-        let test_source = Ipv4Addr::new(test_a.clone(), test_b.clone(), test_c.clone(), 200);
-        let test_target = Ipv4Addr::new(test_a.clone(), test_b.clone(), test_c.clone(), 200);
+        let test_source = Ipv4Addr::new(test_a, test_b, test_c, 200);
+        let test_target = Ipv4Addr::new(test_a, test_b, test_c, 200);
 
         let actual_result = is_in_same_subnet(&test_source, &test_target);
-        assert_eq!(actual_result, true);
+        assert!(actual_result);
     }
 }
 
@@ -359,7 +920,7 @@ mod is_same_ip_tests {
         let test_source = "1.2.3.4".parse::<Ipv4Addr>().unwrap();
         let test_target = "1.2.3.4".parse::<Ipv4Addr>().unwrap();
 
-        assert_eq!(is_same_ip(&test_source, &test_target), true);
+        assert!(is_same_ip(&test_source, &test_target));
     }
 
     #[test]
@@ -367,7 +928,154 @@ mod is_same_ip_tests {
         let test_source = "4.3.2.1".parse::<Ipv4Addr>().unwrap();
         let test_target = "1.2.3.4".parse::<Ipv4Addr>().unwrap();
 
-        assert_eq!(is_same_ip(&test_source, &test_target), false);
+        assert!(!is_same_ip(&test_source, &test_target));
+    }
+}
+
+#[cfg(test)]
+mod parse_range_list_tests {
+    use super::*;
+
+    #[test]
+    fn when_ranges_and_numbers_should_return_sorted_numbers() {
+        let actual_result = parse_range_list("500-502,250, 1-3").unwrap();
+
+        assert_eq!(actual_result, vec![1, 2, 3, 250, 500, 501, 502]);
+    }
+
+    #[test]
+    fn when_overlapping_ranges_should_remove_duplicates() {
+        let actual_result = parse_range_list("1-5,3-7,5").unwrap();
+
+        assert_eq!(actual_result, vec![1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn when_reversed_range_should_throw_error() {
+        let actual_error_message = parse_range_list("10-1").expect_err("blah");
+        assert_eq!(actual_error_message, "Invalid range: 10-1");
+    }
+
+    #[test]
+    fn when_not_a_number_should_throw_error() {
+        let actual_error_message = parse_range_list("1-100,abc").expect_err("blah");
+        assert_eq!(actual_error_message, "Invalid range: abc");
+    }
+}
+
+#[cfg(test)]
+mod format_range_list_tests {
+    use super::*;
+
+    #[test]
+    fn when_consecutive_numbers_should_join_ranges() {
+        assert_eq!(
+            format_range_list(&[250, 3, 1, 2, 2, 500, 501]),
+            "1-3,250,500-501"
+        );
+        assert_eq!(format_range_list(&[]), "");
+    }
+
+    #[test]
+    fn when_parsed_back_should_round_trip() {
+        let test_numbers = vec![1, 2, 3, 7, 9, 10, 1000];
+
+        let actual_result = parse_range_list(&format_range_list(&test_numbers)).unwrap();
+
+        assert_eq!(actual_result, test_numbers);
+    }
+}
+
+#[cfg(test)]
+mod parse_country_list_tests {
+    use super::*;
+
+    #[test]
+    fn when_codes_listed_should_return_countries() {
+        let actual_result = parse_country_list("au, DE").unwrap();
+
+        assert_eq!(actual_result.len(), 2);
+        assert_eq!(actual_result[1].to_string(), "de");
+    }
+
+    #[test]
+    fn when_all_should_return_every_country() {
+        let actual_result = parse_country_list("all").unwrap();
+
+        assert_eq!(actual_result.len(), CountryCode::all().len());
+    }
+
+    #[test]
+    fn when_unknown_or_empty_should_throw_error() {
+        assert_eq!(
+            parse_country_list("au,zz").expect_err("blah"),
+            "Unknown country code: zz"
+        );
+        assert!(parse_country_list(" , ").is_err());
+    }
+}
+
+#[cfg(test)]
+mod get_match_type_tests {
+    use super::enum_lookup_outcome::ResolvedHost;
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn build_filter() -> SearchFilter {
+        SearchFilter::new(vec![Ipv4Addr::new(1, 2, 3, 4)])
+    }
+
+    fn build_outcome(addresses: Vec<Ipv4Addr>) -> LookupOutcome {
+        LookupOutcome::Resolved(ResolvedHost {
+            addresses,
+            cnames: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn when_resolved_same_ip_should_return_exact() {
+        let test_outcome = build_outcome(vec![Ipv4Addr::new(1, 2, 3, 4)]);
+
+        let actual_result = get_match_type(&test_outcome, &build_filter());
+        assert!(matches!(actual_result, (MatchType::Exact, Some(_))));
+    }
+
+    #[test]
+    fn when_resolved_same_subnet_should_return_partial() {
+        let test_outcome = build_outcome(vec![Ipv4Addr::new(1, 2, 3, 200)]);
+
+        let actual_result = get_match_type(&test_outcome, &build_filter());
+        assert!(matches!(actual_result, (MatchType::Partial, Some(_))));
+    }
+
+    #[test]
+    fn when_second_record_exact_should_return_exact_with_matched_record() {
+        let test_outcome = build_outcome(vec![
+            Ipv4Addr::new(1, 2, 3, 200),
+            Ipv4Addr::new(9, 9, 9, 9),
+            Ipv4Addr::new(1, 2, 3, 4),
+        ]);
+
+        let actual_result = get_match_type(&test_outcome, &build_filter());
+        assert!(
+            matches!(actual_result, (MatchType::Exact, Some(ip)) if ip == Ipv4Addr::new(1, 2, 3, 4))
+        );
+    }
+
+    #[test]
+    fn when_second_target_exact_should_return_exact() {
+        let test_filter =
+            SearchFilter::new(vec![Ipv4Addr::new(9, 9, 9, 1), Ipv4Addr::new(5, 6, 7, 8)]);
+        let test_outcome = build_outcome(vec![Ipv4Addr::new(5, 6, 7, 8)]);
+
+        let actual_result = get_match_type(&test_outcome, &test_filter);
+        assert!(matches!(actual_result, (MatchType::Exact, Some(_))));
+    }
+
+    #[test]
+    fn when_nxdomain_should_return_none() {
+        let actual_result = get_match_type(&LookupOutcome::NxDomain, &build_filter());
+        assert!(matches!(actual_result, (MatchType::None, None)));
     }
 }
 
@@ -400,9 +1108,8 @@ mod is_valid_ip_tests {
     }
 
     #[test]
-    fn when_valid_ip_should_return_brackets() {
-        let actual_result =
-            is_valid_ip("1.2.3.4".to_string()).expect("should of gotten a string back");
-        assert_eq!(actual_result, ());
+    fn when_valid_ip_should_return_ok() {
+        let actual_result = is_valid_ip("1.2.3.4".to_string());
+        assert!(actual_result.is_ok());
     }
 }
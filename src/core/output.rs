@@ -0,0 +1,45 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::progress::{IndicatifProgress, MatchPrinter, NoProgress, ScanProgress};
+use console::Term;
+
+// How a search prints, stdout only ever carries results unless both streams are terminals
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Output {
+    Pretty,    // progress bars, matches & the emoji report (a terminal)
+    Plain,     // matches to stdout, the report to stderr, no progress (piped)
+    Quiet,     // matches only (--quiet)
+    Porcelain, // one stable `exact<TAB>host<TAB>ip` line per match (--porcelain)
+    Silent,    // nothing at all, E.g. a started scan reporting through its events
+}
+
+impl Output {
+    // Pretty when attached to a terminal, Plain when stdout or stderr is redirected
+    pub fn detect() -> Output {
+        if Term::stdout().is_term() && Term::stderr().is_term() {
+            Output::Pretty
+        } else {
+            Output::Plain
+        }
+    }
+
+    // Informational messages: headers, summaries, why the search ended
+    pub fn report(&self, message: &str) {
+        match self {
+            Output::Pretty => println!("{}", message),
+            Output::Plain => eprintln!("{}", message),
+            Output::Quiet | Output::Porcelain | Output::Silent => {}
+        }
+    }
+
+    pub fn progress(&self) -> Box<dyn ScanProgress> {
+        match self {
+            Output::Pretty => Box::new(IndicatifProgress::default()),
+            Output::Plain | Output::Quiet => Box::new(MatchPrinter::new(false)),
+            Output::Porcelain => Box::new(MatchPrinter::new(true)),
+            Output::Silent => Box::new(NoProgress),
+        }
+    }
+}
@@ -0,0 +1,346 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::enum_lookup_outcome::{LookupOutcome, LookupSummary};
+use super::worker_models::WorkerResponse;
+use console::Term;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::io::{self, Write};
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+// Receives progress from a running search, so the engine never talks to a terminal itself
+// - called from every worker thread, implementations must be cheap & thread safe
+pub trait ScanProgress: Send + Sync {
+    // workers are about to start, with how many hostnames each one will scan
+    fn started(&self, worker_loads: &[usize]);
+
+    // a worker is done with a hostname, looked up or skipped past the fleet edge
+    fn host_scanned(&self, worker: usize, hostname: &str);
+
+    // an exact or partial match, in the order results arrive
+    fn matched(&self, response: &WorkerResponse);
+
+    // every worker has stopped, whether done, stopped early or panicked
+    fn finished(&self, summary: &LookupSummary);
+}
+
+// Reports nothing, E.g. for headless library use
+pub struct NoProgress;
+
+impl ScanProgress for NoProgress {
+    fn started(&self, _worker_loads: &[usize]) {}
+
+    fn host_scanned(&self, _worker: usize, _hostname: &str) {}
+
+    fn matched(&self, _response: &WorkerResponse) {}
+
+    fn finished(&self, _summary: &LookupSummary) {}
+}
+
+// Plain lines for logs: matches to stdout, every 10% of progress to stderr
+#[derive(Default)]
+pub struct LogProgress {
+    total: AtomicUsize,
+    scanned: AtomicUsize,
+}
+
+impl ScanProgress for LogProgress {
+    fn started(&self, worker_loads: &[usize]) {
+        let total: usize = worker_loads.iter().sum();
+        self.total.store(total, Ordering::SeqCst);
+        eprintln!(
+            "scanning {} hostnames with {} workers",
+            total,
+            worker_loads.len()
+        );
+    }
+
+    fn host_scanned(&self, _worker: usize, _hostname: &str) {
+        let scanned = self.scanned.fetch_add(1, Ordering::SeqCst) + 1;
+        let total = self.total.load(Ordering::SeqCst);
+        if let Some(percent) = milestone(scanned, total) {
+            eprintln!("scanned {} of {} hostnames ({}%)", scanned, total, percent);
+        }
+    }
+
+    fn matched(&self, response: &WorkerResponse) {
+        if let Some(ip) = response.ip {
+            println!("{}", format_match(response, &ip));
+        }
+    }
+
+    fn finished(&self, summary: &LookupSummary) {
+        eprintln!("finished, {} hostnames looked up", summary.total());
+    }
+}
+
+// Matches only, no progress (piped output, --quiet & --porcelain)
+pub struct MatchPrinter {
+    porcelain: bool,
+}
+
+impl MatchPrinter {
+    pub fn new(porcelain: bool) -> MatchPrinter {
+        MatchPrinter { porcelain }
+    }
+}
+
+impl ScanProgress for MatchPrinter {
+    fn started(&self, _worker_loads: &[usize]) {}
+
+    fn host_scanned(&self, _worker: usize, _hostname: &str) {}
+
+    // a closed pipe (E.g. `| head -1`) only ends the output, never the search
+    fn matched(&self, response: &WorkerResponse) {
+        if let Some(ip) = response.ip {
+            let line = if self.porcelain {
+                format_porcelain(response, &ip)
+            } else {
+                format_match(response, &ip)
+            };
+            let _ = writeln!(io::stdout(), "{}", line);
+        }
+    }
+
+    fn finished(&self, _summary: &LookupSummary) {}
+}
+
+struct Bars {
+    overall: ProgressBar,
+    workers: Vec<ProgressBar>,
+    printer: ProgressBar,
+    draw: Option<thread::JoinHandle<()>>,
+}
+
+// One overall bar with throughput, matches & ETA, matches printed above it (the default for a terminal)
+// - optionally one bar per worker underneath (--per-worker-progress)
+// - indicatif hides the bars by itself when stderr isn't a terminal
+#[derive(Default)]
+pub struct IndicatifProgress {
+    per_worker: bool,
+    matches: AtomicUsize,
+    bars: Mutex<Option<Bars>>,
+}
+
+impl IndicatifProgress {
+    pub fn new(per_worker: bool) -> IndicatifProgress {
+        IndicatifProgress {
+            per_worker,
+            ..IndicatifProgress::default()
+        }
+    }
+}
+
+impl ScanProgress for IndicatifProgress {
+    fn started(&self, worker_loads: &[usize]) {
+        let m = MultiProgress::new();
+        let total: usize = worker_loads.iter().sum();
+
+        // results are printed above the progress bars while they are drawn, straight to stdout otherwise
+        let printer = m.add(ProgressBar::new(total as u64));
+        printer.set_style(ProgressStyle::default_bar().template("{msg}"));
+
+        let overall = m.add(ProgressBar::new(total as u64));
+        overall.set_style(
+            ProgressStyle::default_bar()
+                .template("[{elapsed_precise}]  {bar:20} {pos}/{len} hostnames, {per_sec}, {msg}, ETA {eta}")
+                .progress_chars("◼︎□■."),
+        );
+        overall.set_message(&format_matches(0));
+
+        let sty = ProgressStyle::default_bar()
+            .template("[{elapsed_precise}]  {bar:13} {spinner} {msg}")
+            .progress_chars("◼︎□■.");
+        let workers: Vec<ProgressBar> = worker_loads
+            .iter()
+            .filter(|_| self.per_worker)
+            .map(|scan_count| {
+                let pb = m.add(ProgressBar::new(*scan_count as u64));
+                pb.set_style(sty.clone());
+                pb
+            })
+            .collect();
+
+        // draw progress bars on their own thread
+        let draw = thread::spawn(move || {
+            let _ = m.join_and_clear();
+        });
+
+        *self.bars.lock().unwrap() = Some(Bars {
+            overall,
+            workers,
+            printer,
+            draw: Some(draw),
+        });
+    }
+
+    fn host_scanned(&self, worker: usize, hostname: &str) {
+        // bars are cheap handles, nothing is drawn while holding the lock
+        let (overall, pb) = match self.bars.lock().unwrap().as_ref() {
+            Some(bars) => (bars.overall.clone(), bars.workers.get(worker).cloned()),
+            None => return,
+        };
+        overall.inc(1);
+        if let Some(pb) = pb {
+            pb.set_message(&format!("🔎 [scanned: {}]", hostname));
+            pb.inc(1);
+            if pb.position() >= pb.length() {
+                pb.finish_with_message("✨Done✨");
+            }
+        }
+    }
+
+    fn matched(&self, response: &WorkerResponse) {
+        let ip = match response.ip {
+            Some(ip) => ip,
+            None => return,
+        };
+        let line = format_match(response, &ip);
+        let matches = self.matches.fetch_add(1, Ordering::SeqCst) + 1;
+
+        let bars = self
+            .bars
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|bars| (bars.overall.clone(), bars.printer.clone()));
+        if let Some((overall, _)) = &bars {
+            overall.set_message(&format_matches(matches));
+        }
+        match bars {
+            Some((_, printer)) if Term::stdout().is_term() && Term::stderr().is_term() => {
+                printer.println(line)
+            }
+            _ => println!("{}", line),
+        }
+    }
+
+    // the draw thread only returns once every bar is finished
+    fn finished(&self, _summary: &LookupSummary) {
+        let mut bars = match self.bars.lock().unwrap().take() {
+            Some(bars) => bars,
+            None => return,
+        };
+        for pb in bars.workers.iter().filter(|pb| !pb.is_finished()) {
+            pb.finish_with_message("⏹ Stopped");
+        }
+        bars.overall.finish();
+        bars.printer.finish_and_clear();
+        if let Some(draw) = bars.draw.take() {
+            let _ = draw.join();
+        }
+    }
+}
+
+// Util: match count shown on the overall bar
+fn format_matches(matches: usize) -> String {
+    match matches {
+        1 => String::from("1 match"),
+        _ => format!("{} matches", matches),
+    }
+}
+
+// Info: a match, with the full record set when the hostname has several A records or CNAMEs
+pub(super) fn format_match(data: &WorkerResponse, ip: &Ipv4Addr) -> String {
+    match &data.outcome {
+        LookupOutcome::Resolved(host) if host.addresses.len() > 1 || !host.cnames.is_empty() => {
+            format!(
+                "[{}]\t[ {}, {}]\t({})",
+                &data.match_type, &data.host, ip, host
+            )
+        }
+        _ => format!("[{}]\t[ {}, {}]", &data.match_type, &data.host, ip),
+    }
+}
+
+// Info: a match for scripts, E.g. `exact<TAB>au42.nordvpn.com<TAB>1.2.3.4`
+// - this format is stable, new information only ever goes into extra columns
+fn format_porcelain(data: &WorkerResponse, ip: &Ipv4Addr) -> String {
+    format!("{}\t{}\t{}", data.match_type.label(), &data.host, ip)
+}
+
+// Util: the percentage to log when `scanned` crosses another 10% of `total`
+fn milestone(scanned: usize, total: usize) -> Option<usize> {
+    if total == 0 {
+        return None;
+    }
+    let percent = scanned * 100 / total;
+    let previous = (scanned - 1) * 100 / total;
+    if percent / 10 > previous / 10 {
+        Some(percent / 10 * 10)
+    } else {
+        None
+    }
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod format_porcelain_tests {
+    use super::*;
+    use crate::core::enum_country_code::CountryCode;
+    use crate::core::enum_lookup_outcome::ResolvedHost;
+    use crate::core::enum_match_type::MatchType;
+
+    #[test]
+    fn when_exact_match_should_return_tab_separated_line() {
+        let test_ip = Ipv4Addr::new(1, 2, 3, 4);
+        let test_response = WorkerResponse {
+            country_code: CountryCode::Au,
+            num: 42,
+            match_type: MatchType::Exact,
+            host: String::from("au42.nordvpn.com"),
+            ip: Some(test_ip),
+            outcome: LookupOutcome::Resolved(ResolvedHost {
+                addresses: vec![Ipv4Addr::new(5, 6, 7, 8), test_ip],
+                cnames: vec![String::from("lb.nordvpn.com")],
+            }),
+        };
+
+        assert_eq!(
+            format_porcelain(&test_response, &test_ip),
+            "exact\tau42.nordvpn.com\t1.2.3.4"
+        );
+    }
+}
+
+#[cfg(test)]
+mod format_matches_tests {
+    use super::*;
+
+    #[test]
+    fn when_one_match_should_return_singular() {
+        assert_eq!(format_matches(0), "0 matches");
+        assert_eq!(format_matches(1), "1 match");
+        assert_eq!(format_matches(12), "12 matches");
+    }
+}
+
+#[cfg(test)]
+mod milestone_tests {
+    use super::*;
+
+    #[test]
+    fn when_crossing_ten_percent_should_return_milestone() {
+        assert_eq!(milestone(10, 100), Some(10));
+        assert_eq!(milestone(11, 100), None);
+        assert_eq!(milestone(100, 100), Some(100));
+    }
+
+    #[test]
+    fn when_total_small_should_log_every_step() {
+        let actual_result: Vec<Option<usize>> = (1..=3).map(|item| milestone(item, 3)).collect();
+
+        assert_eq!(actual_result, vec![Some(30), Some(60), Some(100)]);
+    }
+
+    #[test]
+    fn when_total_zero_should_never_log() {
+        assert_eq!(milestone(1, 0), None);
+    }
+}
@@ -0,0 +1,698 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::checkpoint::Checkpoint;
+use super::daemon::run_on_daemon;
+use super::dns_resolver::DnsResolver;
+use super::enum_country_code::CountryCode;
+use super::enum_exit_code::ExitCode;
+use super::enum_lookup_outcome::{LookupOutcome, LookupSummary};
+use super::enum_match_type::MatchType;
+use super::host_cache::{unix_now, HostCache};
+use super::interrupt::Interrupt;
+use super::output::Output;
+use super::progress::ScanProgress;
+use super::worker_models::{
+    Concurrency, LookupPolicy, ScanControl, SearchFilter, WorkerRequest, WorkerResponse,
+};
+use super::{build_data, discover_range, entry_point_mt, panic_message, prioritize, shuffle};
+use std::net::Ipv4Addr;
+use std::ops::RangeInclusive;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+// Builder for a search, E.g.
+//   let results = Scan::builder()
+//       .country(CountryCode::Au)
+//       .range(1..=1000)
+//       .target("1.2.3.4".parse().unwrap())
+//       .build()?
+//       .run()?;
+pub struct ScanBuilder {
+    countries: Vec<CountryCode>,
+    numbers: Vec<u32>,
+    targets: Vec<Ipv4Addr>,
+    first: Option<bool>,
+    collect_table: bool,
+    resolver: Option<DnsResolver>,
+    concurrency: Concurrency,
+    policy: LookupPolicy,
+    checkpoint: Option<Checkpoint>,
+    cache: Option<HostCache>,
+    auto_range: Option<u32>,
+    max_gap: Option<u32>,
+    shuffle: bool,
+    output: Option<Output>,
+    progress: Option<Box<dyn ScanProgress>>,
+    daemon: Option<String>,
+    interrupt: Option<Interrupt>,
+}
+
+// A configured search, ready to run
+pub struct Scan {
+    pub(super) requests: Vec<WorkerRequest>,
+    pub(super) filter: SearchFilter,
+    pub(super) collect_table: bool,
+    pub(super) resolver: DnsResolver,
+    pub(super) concurrency: Concurrency,
+    pub(super) policy: LookupPolicy,
+    pub(super) checkpoint: Option<Checkpoint>,
+    pub(super) cache: Option<HostCache>,
+    pub(super) auto_range: Option<u32>,
+    pub(super) max_gap: Option<u32>,
+    pub(super) shuffle: bool,
+    pub(super) output: Option<Output>,
+    pub(super) progress: Option<Box<dyn ScanProgress>>,
+    pub(super) daemon: Option<String>,
+    pub(super) interrupt: Option<Interrupt>,
+}
+
+// Every lookup outcome of a search, in the order they completed
+pub struct ScanResults {
+    pub responses: Vec<WorkerResponse>,
+    pub summary: LookupSummary,
+    pub interrupted: bool, // stopped by an Interrupt or ScanHandle::cancel
+    pub cache_error: Option<String>, // the cache couldn't be saved, the search itself is fine
+}
+
+// What a started search reports, as it happens
+pub enum ScanEvent {
+    Match(WorkerResponse),                 // exact or partial match
+    LookupDone(WorkerResponse),            // resolved without a match, or NXDOMAIN
+    Error(WorkerResponse),                 // timeout or resolver error, after retries
+    Finished(Result<ScanResults, String>), // always the last event
+}
+
+// A search running in the background, E.g.
+//   let handle = Scan::builder()...build()?.start();
+//   for event in handle {
+//       if let ScanEvent::Match(item) = event { ... }
+//   }
+// - dropping the handle stops the search at its next lookup
+pub struct ScanHandle {
+    events: mpsc::Receiver<ScanEvent>,
+    control: Arc<ScanControl>,
+}
+
+impl Scan {
+    pub fn builder() -> ScanBuilder {
+        ScanBuilder {
+            countries: Vec::new(),
+            numbers: build_data(1, 1000),
+            targets: Vec::new(),
+            first: None,
+            collect_table: false,
+            resolver: None,
+            concurrency: Concurrency::Fixed(num_cpus::get()),
+            policy: LookupPolicy::new(Duration::from_secs(5), 2),
+            checkpoint: None,
+            cache: None,
+            auto_range: None,
+            max_gap: None,
+            shuffle: false,
+            output: None,
+            progress: None,
+            daemon: None,
+            interrupt: None,
+        }
+    }
+
+    // Run the search to completion (or early exit / interrupt), printing progress & matches
+    pub fn run(mut self) -> Result<ScanResults, String> {
+        self.output.get_or_insert_with(Output::detect);
+        let control = Arc::new(ScanControl::default());
+        if let Some(interrupt) = &self.interrupt {
+            interrupt.register(&control);
+        }
+
+        // checkpoints, auto range & max gap need the local engine
+        let delegate =
+            self.checkpoint.is_none() && self.auto_range.is_none() && self.max_gap.is_none();
+        if let Some(socket_path) = self.daemon.take().filter(|_| delegate) {
+            if let Some(results) = run_on_daemon(&socket_path, &mut self, &control) {
+                return results;
+            }
+        }
+        self.execute(control, None)
+    }
+
+    // Run the search on a background thread, reporting every lookup as a ScanEvent
+    // - nothing is printed unless an output was chosen
+    pub fn start(mut self) -> ScanHandle {
+        self.output.get_or_insert(Output::Silent);
+        let (tx, rx) = mpsc::channel();
+        let control = Arc::new(ScanControl::default());
+        if let Some(interrupt) = &self.interrupt {
+            interrupt.register(&control);
+        }
+
+        let scan_control = Arc::clone(&control);
+        thread::spawn(move || {
+            let finished_tx = tx.clone();
+            let result =
+                panic::catch_unwind(AssertUnwindSafe(|| self.execute(scan_control, Some(tx))))
+                    .unwrap_or_else(|payload| {
+                        Err(format!("Scan panicked: {}", panic_message(&payload)))
+                    });
+            let _ = finished_tx.send(ScanEvent::Finished(result));
+        });
+
+        ScanHandle {
+            events: rx,
+            control,
+        }
+    }
+
+    fn execute(
+        mut self,
+        control: Arc<ScanControl>,
+        events: Option<mpsc::Sender<ScanEvent>>,
+    ) -> Result<ScanResults, String> {
+        let output = self.output.unwrap_or(Output::Silent);
+        if let Some(gap_tolerance) = self.auto_range {
+            self.requests = discover_requests(
+                self.requests,
+                gap_tolerance,
+                self.concurrency,
+                &self.resolver,
+                &self.policy,
+                &control,
+                output,
+            );
+        }
+        if self.requests.is_empty() {
+            return Err(String::from("No live servers found to scan"));
+        }
+
+        if self.shuffle {
+            for request in self.requests.iter_mut() {
+                shuffle(&mut request.data);
+            }
+        }
+
+        let mut cache = self.cache.take();
+        if let Some(cache) = &cache {
+            let prioritized = prioritize(&mut self.requests, cache, &self.filter, unix_now());
+            if prioritized > 0 {
+                output.report(&format!(
+                    "🎯 {} hostnames prioritized from cache (target subnet neighbors, recently changed)\n",
+                    prioritized
+                ));
+            }
+        }
+
+        let mut results = entry_point_mt(self, control, events)?;
+
+        // a broken cache only costs the prioritized scan order next time, never the search
+        if let Some(cache) = &mut cache {
+            let now = unix_now();
+            for item in results.responses.iter() {
+                cache.update(&item.country_code, item.num, &item.outcome, now);
+            }
+            results.cache_error = cache.save().err();
+        }
+
+        Ok(results)
+    }
+}
+
+impl ScanBuilder {
+    pub fn country(mut self, country_code: CountryCode) -> ScanBuilder {
+        self.countries.push(country_code);
+        self
+    }
+
+    pub fn countries(mut self, country_codes: Vec<CountryCode>) -> ScanBuilder {
+        self.countries.extend(country_codes);
+        self
+    }
+
+    // Contiguous server numbers to scan [Default: 1..=1000]
+    pub fn range(mut self, range: RangeInclusive<u32>) -> ScanBuilder {
+        self.numbers = range.collect();
+        self
+    }
+
+    // Sparse server numbers to scan, E.g. from parse_range_list
+    pub fn numbers(mut self, mut numbers: Vec<u32>) -> ScanBuilder {
+        numbers.sort_unstable();
+        numbers.dedup();
+        self.numbers = numbers;
+        self
+    }
+
+    pub fn target(mut self, ip: Ipv4Addr) -> ScanBuilder {
+        self.targets.push(ip);
+        self
+    }
+
+    pub fn targets(mut self, ips: Vec<Ipv4Addr>) -> ScanBuilder {
+        self.targets.extend(ips);
+        self
+    }
+
+    // Stop once every target has an exact match [Default: only when searching for a single ip]
+    pub fn first(mut self, first: bool) -> ScanBuilder {
+        self.first = Some(first);
+        self
+    }
+
+    // Search for nothing, only resolve every hostname into ScanResults.responses, E.g. to diff two sweeps
+    // - no targets can be given, so no host is ever reported as a match
+    pub fn collect_table(mut self, collect_table: bool) -> ScanBuilder {
+        self.collect_table = collect_table;
+        self
+    }
+
+    // [Default: first nameserver in /etc/resolv.conf]
+    pub fn resolver(mut self, resolver: DnsResolver) -> ScanBuilder {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    // [Default: one thread per logical CPU]
+    pub fn concurrency(mut self, concurrency: Concurrency) -> ScanBuilder {
+        self.concurrency = concurrency;
+        self
+    }
+
+    // Timeout, retries & rate limiting [Default: 5s timeout, 2 retries]
+    pub fn policy(mut self, policy: LookupPolicy) -> ScanBuilder {
+        self.policy = policy;
+        self
+    }
+
+    pub fn checkpoint(mut self, checkpoint: Checkpoint) -> ScanBuilder {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    // Cache of resolved hostnames, used to scan likely matches first and updated afterwards
+    pub fn cache(mut self, cache: HostCache) -> ScanBuilder {
+        self.cache = Some(cache);
+        self
+    }
+
+    // Discover each country's highest live server before scanning, replacing the end of the range
+    pub fn auto_range(mut self, gap_tolerance: u32) -> ScanBuilder {
+        self.auto_range = Some(gap_tolerance);
+        self
+    }
+
+    pub fn max_gap(mut self, max_gap: u32) -> ScanBuilder {
+        self.max_gap = Some(max_gap);
+        self
+    }
+
+    pub fn shuffle(mut self, shuffle: bool) -> ScanBuilder {
+        self.shuffle = shuffle;
+        self
+    }
+
+    // [Default: Output::detect() for run(), Output::Silent for start()]
+    pub fn output(mut self, output: Output) -> ScanBuilder {
+        self.output = Some(output);
+        self
+    }
+
+    // [Default: from the output, E.g. progress bars for Output::Pretty]
+    pub fn progress(mut self, progress: impl ScanProgress + 'static) -> ScanBuilder {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    // Hand run() over to the fns daemon listening on this socket, if there is one
+    // - the daemon's own resolver, concurrency & policy are used, and it shares results with other searches
+    pub fn daemon(mut self, socket_path: &str) -> ScanBuilder {
+        self.daemon = Some(socket_path.to_string());
+        self
+    }
+
+    // Stop the search when the interrupt is triggered, E.g. from the caller's Ctrl-C handler
+    // - partial results are still returned, with interrupted set
+    pub fn interrupt(mut self, interrupt: &Interrupt) -> ScanBuilder {
+        self.interrupt = Some(interrupt.clone());
+        self
+    }
+
+    pub fn build(self) -> Result<Scan, String> {
+        if self.countries.is_empty() {
+            return Err(String::from("At least one Country Code is required"));
+        }
+        if self.collect_table && !self.targets.is_empty() {
+            return Err(String::from(
+                "No Ip4 Address can be searched for while collecting the table",
+            ));
+        }
+        if !self.collect_table && self.targets.is_empty() {
+            return Err(String::from("At least one Ip4 Address is required"));
+        }
+        if self.numbers.is_empty() {
+            return Err(String::from("No server numbers to scan"));
+        }
+
+        let mut filter = SearchFilter::new(self.targets);
+        if let Some(first) = self.first {
+            filter.first = first && !self.collect_table;
+        }
+
+        let resolver = match self.resolver {
+            Some(resolver) => resolver,
+            None => DnsResolver::from_system()?,
+        };
+
+        let numbers = self.numbers;
+        let requests = self
+            .countries
+            .into_iter()
+            .map(|country_code| WorkerRequest::new(country_code, numbers.clone()))
+            .collect();
+
+        Ok(Scan {
+            requests,
+            filter,
+            collect_table: self.collect_table,
+            resolver,
+            concurrency: self.concurrency,
+            policy: self.policy,
+            checkpoint: self.checkpoint,
+            cache: self.cache,
+            auto_range: self.auto_range,
+            max_gap: self.max_gap,
+            shuffle: self.shuffle,
+            output: self.output,
+            progress: self.progress,
+            daemon: self.daemon,
+            interrupt: self.interrupt,
+        })
+    }
+}
+
+impl ScanEvent {
+    pub(super) fn from_response(response: WorkerResponse) -> ScanEvent {
+        match (&response.ip, &response.outcome) {
+            (Some(_), _) => ScanEvent::Match(response),
+            (None, LookupOutcome::Timeout) | (None, LookupOutcome::Error(_)) => {
+                ScanEvent::Error(response)
+            }
+            (None, _) => ScanEvent::LookupDone(response),
+        }
+    }
+}
+
+impl ScanHandle {
+    // Stop the search, a Finished event with the partial results still follows
+    pub fn cancel(&self) {
+        self.control.interrupt();
+    }
+
+    // Hold every worker before its next lookup, lookups already in flight still complete
+    pub fn pause(&self) {
+        self.control.pause();
+    }
+
+    pub fn resume(&self) {
+        self.control.resume();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.control.is_paused()
+    }
+
+    // The raw channel, E.g. for recv_timeout from a UI loop
+    pub fn events(&self) -> &mpsc::Receiver<ScanEvent> {
+        &self.events
+    }
+}
+
+impl Iterator for ScanHandle {
+    type Item = ScanEvent;
+
+    // blocks until the next event, None once Finished has been received
+    fn next(&mut self) -> Option<ScanEvent> {
+        self.events.recv().ok()
+    }
+}
+
+impl ScanResults {
+    // Exact & partial matches
+    pub fn matches(&self) -> impl Iterator<Item = &WorkerResponse> {
+        self.responses.iter().filter(|item| item.ip.is_some())
+    }
+
+    pub fn exact_matches(&self) -> impl Iterator<Item = &WorkerResponse> {
+        self.responses
+            .iter()
+            .filter(|item| matches!(item.match_type, MatchType::Exact))
+    }
+
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from_results(&self.responses, self.interrupted)
+    }
+}
+
+// Util: replace the end of each country's range with its highest live server
+// - countries with no live servers are dropped
+fn discover_requests(
+    requests: Vec<WorkerRequest>,
+    gap_tolerance: u32,
+    concurrency: Concurrency,
+    resolver: &DnsResolver,
+    policy: &LookupPolicy,
+    control: &ScanControl,
+    output: Output,
+) -> Vec<WorkerRequest> {
+    output.report("🔭 Discovering server ranges...\n");
+
+    requests
+        .into_iter()
+        .filter_map(|request| {
+            let start = request.data.iter().min().cloned().unwrap_or(1);
+            match discover_range(
+                &request.country_code,
+                start,
+                gap_tolerance,
+                concurrency.threads(),
+                resolver,
+                policy,
+                control,
+            ) {
+                Some(highest) => {
+                    output.report(&format!(
+                        " - {}: {}-{}",
+                        request.country_code, start, highest
+                    ));
+                    Some(WorkerRequest::new(
+                        request.country_code,
+                        build_data(start, highest),
+                    ))
+                }
+                None => {
+                    output.report(&format!(
+                        " - {}: no live servers found, skipping",
+                        request.country_code
+                    ));
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod scan_builder_tests {
+    use super::*;
+
+    #[test]
+    fn when_no_target_should_return_error() {
+        let actual_result = Scan::builder().country(CountryCode::Au).build();
+
+        assert!(actual_result.is_err());
+    }
+
+    #[test]
+    fn when_single_target_should_default_to_first() {
+        let actual_result = Scan::builder()
+            .country(CountryCode::Au)
+            .range(1..=10)
+            .target(Ipv4Addr::new(1, 2, 3, 4))
+            .resolver(DnsResolver::from_address("127.0.0.1").unwrap())
+            .build()
+            .unwrap();
+
+        assert!(actual_result.filter.first);
+        assert_eq!(actual_result.requests[0].data.len(), 10);
+    }
+
+    #[test]
+    fn when_first_disabled_should_scan_everything() {
+        let actual_result = Scan::builder()
+            .countries(vec![CountryCode::Au, CountryCode::Nz])
+            .numbers(vec![5, 1, 5, 3])
+            .target(Ipv4Addr::new(1, 2, 3, 4))
+            .first(false)
+            .resolver(DnsResolver::from_address("127.0.0.1").unwrap())
+            .build()
+            .unwrap();
+
+        assert!(!actual_result.filter.first);
+        assert_eq!(actual_result.requests.len(), 2);
+        assert_eq!(actual_result.requests[1].data, vec![1, 3, 5]);
+    }
+
+    #[test]
+    fn when_collecting_table_should_search_for_nothing() {
+        let actual_result = Scan::builder()
+            .country(CountryCode::Au)
+            .collect_table(true)
+            .first(true)
+            .resolver(DnsResolver::from_address("127.0.0.1").unwrap())
+            .build()
+            .unwrap();
+
+        assert!(actual_result.filter.ips.is_empty());
+        assert!(!actual_result.filter.first);
+    }
+
+    #[test]
+    fn when_collecting_table_with_target_should_return_error() {
+        let actual_result = Scan::builder()
+            .country(CountryCode::Au)
+            .collect_table(true)
+            .target(Ipv4Addr::UNSPECIFIED)
+            .build();
+
+        assert!(actual_result.is_err());
+    }
+}
+
+#[cfg(test)]
+mod scan_event_tests {
+    use super::*;
+
+    fn test_response(ip: Option<Ipv4Addr>, outcome: LookupOutcome) -> WorkerResponse {
+        WorkerResponse {
+            country_code: CountryCode::Au,
+            num: 1,
+            match_type: MatchType::None,
+            host: String::from("au1.nordvpn.com"),
+            ip,
+            outcome,
+        }
+    }
+
+    #[test]
+    fn when_matched_should_return_match_event() {
+        let test_response = test_response(Some(Ipv4Addr::new(1, 2, 3, 4)), LookupOutcome::NxDomain);
+
+        assert!(matches!(
+            ScanEvent::from_response(test_response),
+            ScanEvent::Match(_)
+        ));
+    }
+
+    #[test]
+    fn when_timeout_or_error_should_return_error_event() {
+        let test_timeout = test_response(None, LookupOutcome::Timeout);
+        let test_error = test_response(None, LookupOutcome::Error(String::from("refused")));
+
+        assert!(matches!(
+            ScanEvent::from_response(test_timeout),
+            ScanEvent::Error(_)
+        ));
+        assert!(matches!(
+            ScanEvent::from_response(test_error),
+            ScanEvent::Error(_)
+        ));
+    }
+
+    #[test]
+    fn when_nxdomain_should_return_lookup_done_event() {
+        let test_response = test_response(None, LookupOutcome::NxDomain);
+
+        assert!(matches!(
+            ScanEvent::from_response(test_response),
+            ScanEvent::LookupDone(_)
+        ));
+    }
+}
+
+#[cfg(test)]
+mod scan_progress_tests {
+    use super::*;
+    use std::net::UdpSocket;
+    use std::sync::Mutex;
+
+    // Counts what the engine reports
+    #[derive(Clone, Default)]
+    struct TestProgress {
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl ScanProgress for TestProgress {
+        fn started(&self, worker_loads: &[usize]) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("started {:?}", worker_loads));
+        }
+
+        fn host_scanned(&self, _worker: usize, hostname: &str) {
+            self.calls.lock().unwrap().push(hostname.to_string());
+        }
+
+        fn matched(&self, response: &WorkerResponse) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("matched {}", response.host));
+        }
+
+        fn finished(&self, summary: &LookupSummary) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("finished {}", summary.total()));
+        }
+    }
+
+    #[test]
+    fn when_headless_scan_should_report_every_host_to_progress() {
+        // a nameserver that never answers, so every lookup times out
+        let test_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let test_progress = TestProgress::default();
+
+        let handle = Scan::builder()
+            .country(CountryCode::Au)
+            .range(1..=5)
+            .target(Ipv4Addr::new(1, 2, 3, 4))
+            .resolver(DnsResolver::new(test_socket.local_addr().unwrap()))
+            .policy(LookupPolicy::new(Duration::from_millis(20), 0))
+            .concurrency(Concurrency::Fixed(2))
+            .progress(test_progress.clone())
+            .build()
+            .unwrap()
+            .start();
+        let finished = handle
+            .filter(|event| matches!(event, ScanEvent::Finished(_)))
+            .count();
+
+        let calls = test_progress.calls.lock().unwrap();
+        assert_eq!(finished, 1);
+        assert_eq!(calls.first().unwrap(), "started [3, 2]");
+        assert_eq!(calls.last().unwrap(), "finished 5");
+        assert_eq!(
+            calls
+                .iter()
+                .filter(|item| item.ends_with(".nordvpn.com"))
+                .count(),
+            5
+        );
+    }
+}
@@ -0,0 +1,187 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::host_cache::{CachedHost, HostCache};
+use super::worker_models::{SearchFilter, WorkerRequest};
+use super::{is_in_same_subnet, is_same_ip};
+
+// Server numbers this close to a host seen in a target's subnet are scanned early
+const NEIGHBOR_RADIUS: u32 = 25;
+
+// Hosts whose addresses changed this recently are scanned early (7 days)
+const RECENT_CHANGE_SECS: u64 = 7 * 24 * 60 * 60;
+
+// How likely a hostname is to match, lowest first
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Priority {
+    CachedExact,
+    CachedSubnet,
+    Neighbor(u32), // distance to the nearest cached subnet host
+    RecentlyChanged,
+    Unknown,
+}
+
+// Reorder each request so the hostnames most likely to match are resolved first:
+// 1. cached as an exact match, 2. cached in a target's /24, 3. numbered near those,
+// 4. addresses changed recently, then everything else in its original order
+// - returns how many hostnames were moved ahead
+pub fn prioritize(
+    requests: &mut [WorkerRequest],
+    cache: &HostCache,
+    filter: &SearchFilter,
+    now: u64,
+) -> usize {
+    let mut prioritized = 0;
+
+    for request in requests.iter_mut() {
+        let country = request.country_code.to_string();
+        let cached: Vec<&CachedHost> = cache
+            .hosts()
+            .filter(|host| host.country_code.to_string() == country)
+            .collect();
+        if cached.is_empty() {
+            continue;
+        }
+
+        let subnet_hosts: Vec<u32> = cached
+            .iter()
+            .filter(|host| in_target_subnet(host, filter))
+            .map(|host| host.num)
+            .collect();
+
+        let priority_of = |num: u32| -> Priority {
+            let host = cached.iter().find(|host| host.num == num);
+            if let Some(host) = host {
+                if host
+                    .addresses
+                    .iter()
+                    .any(|ip| filter.ips.iter().any(|target| is_same_ip(ip, target)))
+                {
+                    return Priority::CachedExact;
+                }
+                if in_target_subnet(host, filter) {
+                    return Priority::CachedSubnet;
+                }
+            }
+
+            let distance = subnet_hosts.iter().map(|item| item.abs_diff(num)).min();
+            match (distance, host) {
+                (Some(distance), _) if distance <= NEIGHBOR_RADIUS => Priority::Neighbor(distance),
+                (_, Some(host)) if now.saturating_sub(host.changed) <= RECENT_CHANGE_SECS => {
+                    Priority::RecentlyChanged
+                }
+                _ => Priority::Unknown,
+            }
+        };
+
+        // stable sort, so hostnames of equal priority keep their order
+        let mut ordered: Vec<(Priority, u32)> = request
+            .data
+            .iter()
+            .map(|num| (priority_of(*num), *num))
+            .collect();
+        ordered.sort_by(|a, b| a.0.cmp(&b.0));
+
+        prioritized += ordered
+            .iter()
+            .filter(|(priority, _)| *priority != Priority::Unknown)
+            .count();
+        request.data = ordered.into_iter().map(|(_, num)| num).collect();
+    }
+
+    prioritized
+}
+
+// Util: any cached address in the same /24 as a target
+fn in_target_subnet(host: &CachedHost, filter: &SearchFilter) -> bool {
+    host.addresses.iter().any(|ip| {
+        filter
+            .ips
+            .iter()
+            .any(|target| is_in_same_subnet(ip, target))
+    })
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod prioritize_tests {
+    use super::*;
+    use crate::core::build_data;
+    use crate::core::enum_country_code::CountryCode;
+    use crate::core::enum_lookup_outcome::{LookupOutcome, ResolvedHost};
+    use std::net::Ipv4Addr;
+
+    const NOW: u64 = 100 * 24 * 60 * 60;
+
+    fn resolved(ip: Ipv4Addr) -> LookupOutcome {
+        LookupOutcome::Resolved(ResolvedHost {
+            addresses: vec![ip],
+            cnames: Vec::new(),
+        })
+    }
+
+    fn test_cache(hosts: &[(u32, Ipv4Addr)]) -> HostCache {
+        let mut cache = HostCache::load("/nonexistent/fns/hosts.tsv").unwrap();
+        for (num, ip) in hosts.iter() {
+            cache.update(&CountryCode::Us, *num, &resolved(*ip), 0);
+        }
+        cache
+    }
+
+    #[test]
+    fn when_cached_exact_match_should_scan_it_first() {
+        let cache = test_cache(&[(500, Ipv4Addr::new(1, 2, 3, 4))]);
+        let filter = SearchFilter::new(vec![Ipv4Addr::new(1, 2, 3, 4)]);
+        let mut requests = vec![WorkerRequest::new(CountryCode::Us, build_data(1, 1000))];
+
+        prioritize(&mut requests, &cache, &filter, NOW);
+
+        assert_eq!(requests[0].data[0], 500);
+        assert_eq!(requests[0].data.len(), 1000);
+    }
+
+    #[test]
+    fn when_subnet_seen_should_scan_neighbors_by_distance() {
+        let cache = test_cache(&[(480, Ipv4Addr::new(1, 2, 3, 99))]);
+        let filter = SearchFilter::new(vec![Ipv4Addr::new(1, 2, 3, 4)]);
+        let mut requests = vec![WorkerRequest::new(CountryCode::Us, build_data(1, 1000))];
+
+        let actual_result = prioritize(&mut requests, &cache, &filter, NOW);
+
+        assert_eq!(&requests[0].data[..5], &[480, 479, 481, 478, 482]);
+        assert_eq!(actual_result, 51);
+    }
+
+    #[test]
+    fn when_recently_changed_should_scan_before_unknown() {
+        let mut cache = test_cache(&[
+            (10, Ipv4Addr::new(9, 9, 9, 9)),
+            (20, Ipv4Addr::new(8, 8, 8, 8)),
+        ]);
+        cache.update(
+            &CountryCode::Us,
+            20,
+            &resolved(Ipv4Addr::new(8, 8, 8, 9)),
+            NOW - 60,
+        );
+        let filter = SearchFilter::new(vec![Ipv4Addr::new(1, 2, 3, 4)]);
+        let mut requests = vec![WorkerRequest::new(CountryCode::Us, build_data(1, 30))];
+
+        prioritize(&mut requests, &cache, &filter, NOW);
+
+        assert_eq!(&requests[0].data[..3], &[20, 1, 2]);
+    }
+
+    #[test]
+    fn when_cache_empty_should_keep_order() {
+        let cache = test_cache(&[]);
+        let filter = SearchFilter::new(vec![Ipv4Addr::new(1, 2, 3, 4)]);
+        let mut requests = vec![WorkerRequest::new(CountryCode::Us, build_data(1, 10))];
+
+        assert_eq!(prioritize(&mut requests, &cache, &filter, NOW), 0);
+        assert_eq!(requests[0].data, build_data(1, 10));
+    }
+}
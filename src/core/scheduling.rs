@@ -0,0 +1,141 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::worker_models::ScanControl;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Longest single sleep while waiting, so a stopped search isn't kept waiting
+const WAIT_SLICE: Duration = Duration::from_millis(50);
+
+static RANDOM_STATE: AtomicU64 = AtomicU64::new(0);
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+// Global token bucket shared by every worker (--rate)
+// - holds at most one second worth of lookups, so bursts stay small
+pub struct RateLimiter {
+    rate: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u32) -> RateLimiter {
+        let rate = f64::from(rate.max(1));
+        RateLimiter {
+            rate,
+            bucket: Mutex::new(Bucket {
+                tokens: rate,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    // Block until a lookup may be sent, returns early if the search is stopped
+    pub fn acquire(&self, control: &ScanControl) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let now = Instant::now();
+                let refill = now.duration_since(bucket.last_refill).as_secs_f64() * self.rate;
+                bucket.tokens = (bucket.tokens + refill).min(self.rate);
+                bucket.last_refill = now;
+
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate)
+            };
+
+            if control.is_stopped() {
+                return;
+            }
+            thread::sleep(wait.min(WAIT_SLICE));
+        }
+    }
+}
+
+// Util: random delay between zero and max (--jitter)
+pub fn jitter_delay(max: Duration) -> Duration {
+    let max_micros = max.as_micros() as u64;
+    if max_micros == 0 {
+        return Duration::from_micros(0);
+    }
+    Duration::from_micros(next_random() % (max_micros + 1))
+}
+
+// Util: Fisher-Yates shuffle of a dataframe (--shuffle)
+pub fn shuffle(numbers: &mut [u32]) {
+    for idx in (1..numbers.len()).rev() {
+        let other = (next_random() % (idx as u64 + 1)) as usize;
+        numbers.swap(idx, other);
+    }
+}
+
+// Util: splitmix64, seeded from the clock, good enough to spread queries out
+fn next_random() -> u64 {
+    let _ = RANDOM_STATE.compare_exchange(
+        0,
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or(1),
+        Ordering::Relaxed,
+        Ordering::Relaxed,
+    );
+
+    let mut z = RANDOM_STATE.fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod scheduling_tests {
+    use super::*;
+
+    #[test]
+    fn when_shuffled_should_keep_every_number() {
+        let mut test_numbers: Vec<u32> = (1..=100).collect();
+
+        shuffle(&mut test_numbers);
+        assert_ne!(test_numbers, (1..=100).collect::<Vec<u32>>());
+
+        test_numbers.sort_unstable();
+        assert_eq!(test_numbers, (1..=100).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn when_jitter_should_stay_below_max() {
+        let test_max = Duration::from_millis(20);
+
+        assert!((0..100).all(|_| jitter_delay(test_max) <= test_max));
+        assert_eq!(
+            jitter_delay(Duration::from_millis(0)),
+            Duration::from_millis(0)
+        );
+    }
+
+    #[test]
+    fn when_bucket_empty_should_wait_for_refill() {
+        let test_limiter = RateLimiter::new(20);
+        let test_control = ScanControl::default();
+        let stop_watch = Instant::now();
+
+        // 20 tokens in the bucket, then one every 50ms
+        for _ in 0..22 {
+            test_limiter.acquire(&test_control);
+        }
+        assert!(stop_watch.elapsed() >= Duration::from_millis(90));
+    }
+}
@@ -0,0 +1,695 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::dns_resolver::DnsResolver;
+use super::enum_country_code::CountryCode;
+use super::enum_lookup_outcome::{LookupOutcome, ResolvedHost};
+use super::enum_match_type::MatchType;
+use super::export::json_string;
+use super::host_cache::{CachedHost, HostCache};
+use super::output::Output;
+use super::progress::NoProgress;
+use super::scan::Scan;
+use super::worker_models::{Concurrency, LookupPolicy, SearchFilter};
+use super::{get_match_type, is_valid_ip, parse_country, parse_country_list};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock, RwLockReadGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Give up on clients that connect but never finish sending a request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Connections handled at once, more are turned away with a 503
+const MAX_CONNECTIONS: usize = 32;
+
+// How long a lookup waits on its sweep before answering from the cache with "complete": false
+const SWEEP_WAIT: Duration = Duration::from_secs(10);
+
+// Sweeps waiting for the worker, lookups needing another are answered from the cache
+const MAX_QUEUED_SWEEPS: usize = 8;
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+}
+
+impl HttpRequest {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+struct HttpResponse {
+    status: u16,
+    body: String,
+}
+
+impl HttpResponse {
+    fn ok(body: String) -> HttpResponse {
+        HttpResponse { status: 200, body }
+    }
+
+    fn error(status: u16, message: &str) -> HttpResponse {
+        HttpResponse {
+            status,
+            body: format!("{{\"error\":{}}}", json_string(message)),
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+struct SweepJob {
+    key: String,
+    countries: Vec<CountryCode>,
+    targets: Vec<Ipv4Addr>,
+}
+
+impl SweepJob {
+    fn new(countries: Vec<CountryCode>, targets: Vec<Ipv4Addr>) -> SweepJob {
+        SweepJob {
+            key: format!("{} {}", join(&countries), join(&targets)),
+            countries,
+            targets,
+        }
+    }
+}
+
+// Sweeps for the worker, the same sweep is only queued once
+#[derive(Default)]
+struct SweepQueue {
+    queued: VecDeque<SweepJob>,
+    running: Option<String>,
+}
+
+impl SweepQueue {
+    fn is_pending(&self, key: &str) -> bool {
+        self.running.as_deref() == Some(key) || self.queued.iter().any(|job| job.key == key)
+    }
+
+    // false when the queue is full
+    fn push(&mut self, job: SweepJob) -> bool {
+        if self.is_pending(&job.key) {
+            return true;
+        }
+        if self.queued.len() >= MAX_QUEUED_SWEEPS {
+            return false;
+        }
+        self.queued.push_back(job);
+        true
+    }
+}
+
+// Answers from the host cache, loaded once & reloaded after each sweep
+// - only the countries in sweep are swept when the cache has no exact match, the rest are cache only
+// - sweeps run one at a time on a worker, lookups wait up to SWEEP_WAIT for theirs
+struct Server {
+    numbers: Vec<u32>,
+    sweep: Vec<CountryCode>,
+    concurrency: Concurrency,
+    resolver: DnsResolver,
+    policy: LookupPolicy,
+    cache_path: String,
+    cache: RwLock<HostCache>,
+    sweeps: Mutex<SweepQueue>,
+    sweeps_changed: Condvar,
+    connections: AtomicUsize,
+}
+
+impl Server {
+    fn cache(&self) -> RwLockReadGuard<'_, HostCache> {
+        self.cache.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn route(&self, request: &HttpRequest) -> HttpResponse {
+        if request.method != "GET" {
+            return HttpResponse::error(405, "Only GET is supported");
+        }
+
+        // a bad query is a 400, an unknown country in the path a 404
+        match request.path.trim_end_matches('/') {
+            "/lookup" => self
+                .lookup(request)
+                .unwrap_or_else(|err| HttpResponse::error(400, &err)),
+            path if path.starts_with("/hosts/") => self
+                .hosts(&path["/hosts/".len()..])
+                .unwrap_or_else(|err| HttpResponse::error(404, &err)),
+            _ => HttpResponse::error(404, "Not found, try /lookup?ip= or /hosts/{country}"),
+        }
+    }
+
+    // GET /lookup?ip=1.2.3.4[,5.6.7.8][&country=au[,de]]
+    fn lookup(&self, request: &HttpRequest) -> Result<HttpResponse, String> {
+        let mut targets = Vec::new();
+        for item in request.param("ip").unwrap_or_default().split(',') {
+            let item = item.trim();
+            is_valid_ip(item.to_string()).map_err(|err| format!("{}: {}", err, item))?;
+            targets.push(item.parse::<Ipv4Addr>().unwrap());
+        }
+        let countries = match request.param("country") {
+            Some(value) => Some(parse_country_list(value)?),
+            None => None,
+        };
+
+        let mut source = "cache";
+        let mut complete = true;
+        if let Some(countries) = &countries {
+            let sweep: Vec<CountryCode> = countries
+                .iter()
+                .filter(|country_code| {
+                    self.sweep
+                        .iter()
+                        .any(|item| item.to_string() == country_code.to_string())
+                })
+                .cloned()
+                .collect();
+            if !sweep.is_empty() && !has_exact_matches(&self.cache(), &targets, countries) {
+                if self.wait_for_sweep(SweepJob::new(sweep, targets.clone())) {
+                    source = "scan";
+                } else {
+                    complete = false;
+                }
+            }
+        }
+
+        let cache = self.cache();
+        let matches: Vec<String> = find_matches(&cache, &targets, countries.as_deref())
+            .iter()
+            .map(|(match_type, host, ip)| {
+                format!(
+                    "{{\"match\":{},\"ip\":{},{}}}",
+                    json_string(match_type.label()),
+                    json_string(&ip.to_string()),
+                    host_fields(host)
+                )
+            })
+            .collect();
+        let ips: Vec<String> = targets
+            .iter()
+            .map(|ip| json_string(&ip.to_string()))
+            .collect();
+
+        Ok(HttpResponse::ok(format!(
+            "{{\"ips\":[{}],\"source\":{},\"complete\":{},\"matches\":[{}]}}",
+            ips.join(","),
+            json_string(source),
+            complete,
+            matches.join(",")
+        )))
+    }
+
+    // GET /hosts/au
+    fn hosts(&self, country: &str) -> Result<HttpResponse, String> {
+        let country_code = parse_country(country)?;
+        let cache = self.cache();
+
+        let mut hosts: Vec<&CachedHost> = cache
+            .hosts()
+            .filter(|host| host.country_code.to_string() == country_code.to_string())
+            .collect();
+        hosts.sort_by_key(|host| host.num);
+        let hosts: Vec<String> = hosts
+            .iter()
+            .map(|host| format!("{{{}}}", host_fields(host)))
+            .collect();
+
+        Ok(HttpResponse::ok(format!(
+            "{{\"country\":{},\"hosts\":[{}]}}",
+            json_string(&country_code.to_string()),
+            hosts.join(",")
+        )))
+    }
+
+    // true once the sweep has run, false if it's still queued after SWEEP_WAIT or the queue is full
+    fn wait_for_sweep(&self, job: SweepJob) -> bool {
+        let deadline = Instant::now() + SWEEP_WAIT;
+        let key = job.key.clone();
+        let mut sweeps = self.sweeps.lock().unwrap_or_else(|err| err.into_inner());
+        if !sweeps.push(job) {
+            return false;
+        }
+        self.sweeps_changed.notify_all();
+
+        while sweeps.is_pending(&key) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            sweeps = self
+                .sweeps_changed
+                .wait_timeout(sweeps, remaining)
+                .unwrap_or_else(|err| err.into_inner())
+                .0;
+        }
+        true
+    }
+
+    // the worker, a sweep that ran while this one was queued may have found the targets already
+    fn run_sweeps(&self) {
+        loop {
+            let job = {
+                let mut sweeps = self.sweeps.lock().unwrap_or_else(|err| err.into_inner());
+                loop {
+                    if let Some(job) = sweeps.queued.pop_front() {
+                        sweeps.running = Some(job.key.clone());
+                        break job;
+                    }
+                    sweeps = self
+                        .sweeps_changed
+                        .wait(sweeps)
+                        .unwrap_or_else(|err| err.into_inner());
+                }
+            };
+
+            if !has_exact_matches(&self.cache(), &job.targets, &job.countries) {
+                if let Err(err) = self.sweep(&job.countries, &job.targets) {
+                    eprintln!("error: {}", err);
+                }
+            }
+
+            self.sweeps
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .running = None;
+            self.sweeps_changed.notify_all();
+        }
+    }
+
+    // the sweep updates the cache file, which is reloaded for the requests that follow
+    fn sweep(&self, countries: &[CountryCode], targets: &[Ipv4Addr]) -> Result<(), String> {
+        println!("🔭 Sweeping {} for {}", join(countries), join(targets));
+
+        let results = Scan::builder()
+            .countries(countries.to_vec())
+            .numbers(self.numbers.clone())
+            .targets(targets.to_vec())
+            .concurrency(self.concurrency)
+            .resolver(self.resolver.clone())
+            .policy(self.policy.clone())
+            .cache(HostCache::load(&self.cache_path)?)
+            .output(Output::Silent)
+            .progress(NoProgress)
+            .build()?
+            .run()?;
+        if let Some(err) = results.cache_error {
+            eprintln!("warning: {}", err);
+        }
+
+        let cache = HostCache::load(&self.cache_path)?;
+        *self.cache.write().unwrap_or_else(|err| err.into_inner()) = cache;
+        Ok(())
+    }
+
+    fn handle(&self, stream: TcpStream) {
+        let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+        let response = match read_request(&stream) {
+            Ok(request) => {
+                let response = self.route(&request);
+                println!("{} {} {}", request.method, request.path, response.status);
+                response
+            }
+            Err(err) => HttpResponse::error(400, &err),
+        };
+        write_response(stream, &response);
+    }
+}
+
+// Util: the whole response in one write, the connection is closed afterwards
+fn write_response(mut stream: TcpStream, response: &HttpResponse) {
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.reason(),
+        response.body.len(),
+        response.body
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+// Util: true once every target has an exact match in one of the countries
+fn has_exact_matches(cache: &HostCache, targets: &[Ipv4Addr], countries: &[CountryCode]) -> bool {
+    let matches = find_matches(cache, targets, Some(countries));
+    targets.iter().all(|target| {
+        matches
+            .iter()
+            .any(|(match_type, _, ip)| matches!(match_type, MatchType::Exact) && ip == target)
+    })
+}
+
+// Util: cached hosts matching the targets, exact matches first
+fn find_matches<'a>(
+    cache: &'a HostCache,
+    targets: &[Ipv4Addr],
+    countries: Option<&[CountryCode]>,
+) -> Vec<(MatchType, &'a CachedHost, Ipv4Addr)> {
+    let filter = SearchFilter::new(targets.to_vec());
+    let mut matches: Vec<(MatchType, &CachedHost, Ipv4Addr)> = cache
+        .hosts()
+        .filter(|host| match countries {
+            Some(countries) => countries
+                .iter()
+                .any(|item| item.to_string() == host.country_code.to_string()),
+            None => true,
+        })
+        .filter_map(|host| {
+            let outcome = LookupOutcome::Resolved(ResolvedHost {
+                addresses: host.addresses.clone(),
+                cnames: Vec::new(),
+            });
+            match get_match_type(&outcome, &filter) {
+                (MatchType::None, _) | (_, None) => None,
+                (match_type, Some(ip)) => Some((match_type, host, ip)),
+            }
+        })
+        .collect();
+    matches.sort_by_key(|(match_type, host, _)| {
+        (
+            matches!(match_type, MatchType::Partial),
+            host.country_code.to_string(),
+            host.num,
+        )
+    });
+    matches
+}
+
+// Util: JSON fields of a cached host, without the braces
+fn host_fields(host: &CachedHost) -> String {
+    let addresses: Vec<String> = host
+        .addresses
+        .iter()
+        .map(|ip| json_string(&ip.to_string()))
+        .collect();
+    format!(
+        "\"host\":{},\"country\":{},\"num\":{},\"addresses\":[{}],\"seen\":{},\"changed\":{}",
+        json_string(&format!("{}{}.nordvpn.com", host.country_code, host.num)),
+        json_string(&host.country_code.to_string()),
+        host.num,
+        addresses.join(","),
+        host.seen,
+        host.changed
+    )
+}
+
+fn join<T: ToString>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+// Util: the request line & headers, the body of a GET is ignored
+fn read_request(stream: &TcpStream) -> Result<HttpRequest, String> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader
+        .read_line(&mut request_line)
+        .map_err(|err| format!("Unable to read request: {}", err))?;
+
+    let mut header = String::new();
+    loop {
+        header.clear();
+        match reader.read_line(&mut header) {
+            Ok(0) => break,
+            Ok(_) if header.trim().is_empty() => break,
+            Ok(_) => {}
+            Err(err) => return Err(format!("Unable to read request: {}", err)),
+        }
+    }
+    parse_request_line(&request_line)
+}
+
+// Util: E.g. "GET /lookup?ip=1.2.3.4&country=au HTTP/1.1"
+fn parse_request_line(line: &str) -> Result<HttpRequest, String> {
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/") => {
+            (method, target)
+        }
+        _ => return Err(format!("Malformed request line: {}", line.trim())),
+    };
+
+    let (path, query) = match target.find('?') {
+        Some(split) => (&target[..split], &target[split + 1..]),
+        None => (target, ""),
+    };
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.find('=') {
+            Some(split) => (
+                percent_decode(&pair[..split]),
+                percent_decode(&pair[split + 1..]),
+            ),
+            None => (percent_decode(pair), String::new()),
+        })
+        .collect();
+
+    Ok(HttpRequest {
+        method: method.to_string(),
+        path: percent_decode(path),
+        query,
+    })
+}
+
+// Util: decode %2C & friends, malformed escapes are kept as they are
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes[i] {
+            b'%' if i + 2 < bytes.len() => std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match (escaped, bytes[i]) {
+            (Some(byte), _) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (None, b'+') => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (None, byte) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+// --Entry Point--
+// - a thread per connection up to MAX_CONNECTIONS, sweeps for lookups the cache can't answer run one at a time on a worker
+// - nothing is swept unless its country is in sweep
+pub fn entry_point_serve(
+    listen: &str,
+    numbers: Vec<u32>,
+    sweep: Vec<CountryCode>,
+    concurrency: Concurrency,
+    resolver: DnsResolver,
+    policy: LookupPolicy,
+    cache_path: String,
+) -> Result<(), String> {
+    let listener = TcpListener::bind(listen)
+        .map_err(|err| format!("Unable to listen on {}: {}", listen, err))?;
+    println!(
+        "🌐 Serving on http://{}, GET /lookup?ip=&country= or /hosts/{{country}}",
+        listen
+    );
+
+    if sweep.is_empty() {
+        println!(
+            "📒 Answering from the cache only, use --sweep to sweep countries it can't answer"
+        );
+    }
+
+    let cache = HostCache::load(&cache_path)?;
+    let server = Arc::new(Server {
+        numbers,
+        sweep,
+        concurrency,
+        resolver,
+        policy,
+        cache_path,
+        cache: RwLock::new(cache),
+        sweeps: Mutex::new(SweepQueue::default()),
+        sweeps_changed: Condvar::new(),
+        connections: AtomicUsize::new(0),
+    });
+    let worker = server.clone();
+    thread::spawn(move || worker.run_sweeps());
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        if server.connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            server.connections.fetch_sub(1, Ordering::SeqCst);
+            write_response(
+                stream,
+                &HttpResponse::error(503, "Too many connections, try again later"),
+            );
+            continue;
+        }
+        let server = server.clone();
+        thread::spawn(move || {
+            server.handle(stream);
+            server.connections.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod parse_request_line_tests {
+    use super::*;
+
+    #[test]
+    fn when_query_string_should_split_params() {
+        let actual_result =
+            parse_request_line("GET /lookup?ip=1.2.3.4%2C5.6.7.8&country=au HTTP/1.1\r\n").unwrap();
+
+        assert_eq!(actual_result.method, "GET");
+        assert_eq!(actual_result.path, "/lookup");
+        assert_eq!(actual_result.param("ip"), Some("1.2.3.4,5.6.7.8"));
+        assert_eq!(actual_result.param("country"), Some("au"));
+        assert_eq!(actual_result.param("missing"), None);
+    }
+
+    #[test]
+    fn when_malformed_should_throw_error() {
+        assert!(parse_request_line("GET /lookup\r\n").is_err());
+        assert!(parse_request_line("").is_err());
+    }
+
+    #[test]
+    fn when_escape_malformed_should_keep_it() {
+        assert_eq!(percent_decode("a%2"), "a%2");
+        assert_eq!(percent_decode("a%zz+b"), "a%zz b");
+    }
+}
+
+#[cfg(test)]
+mod find_matches_tests {
+    use super::*;
+
+    fn test_cache() -> HostCache {
+        // a missing file is an empty cache, nothing is ever saved
+        let mut test_cache = HostCache::load("/nonexistent/fns/hosts.tsv").unwrap();
+        for (country_code, num, ip) in [
+            (CountryCode::De, 7, Ipv4Addr::new(1, 2, 3, 9)),
+            (CountryCode::Au, 12, Ipv4Addr::new(1, 2, 3, 4)),
+            (CountryCode::Au, 3, Ipv4Addr::new(1, 2, 3, 5)),
+            (CountryCode::Au, 5, Ipv4Addr::new(8, 8, 8, 8)),
+        ] {
+            let outcome = LookupOutcome::Resolved(ResolvedHost {
+                addresses: vec![ip],
+                cnames: Vec::new(),
+            });
+            test_cache.update(&country_code, num, &outcome, 100);
+        }
+        test_cache
+    }
+
+    #[test]
+    fn when_exact_and_partial_should_return_exact_first() {
+        let test_cache = test_cache();
+        let test_targets = vec![Ipv4Addr::new(1, 2, 3, 4)];
+
+        let actual_result: Vec<String> = find_matches(&test_cache, &test_targets, None)
+            .iter()
+            .map(|(match_type, host, _)| {
+                format!("{} {}{}", match_type.label(), host.country_code, host.num)
+            })
+            .collect();
+
+        assert_eq!(
+            actual_result,
+            vec!["exact au12", "partial au3", "partial de7"]
+        );
+        assert!(has_exact_matches(
+            &test_cache,
+            &test_targets,
+            &[CountryCode::Au]
+        ));
+        assert!(!has_exact_matches(
+            &test_cache,
+            &test_targets,
+            &[CountryCode::De]
+        ));
+    }
+
+    #[test]
+    fn when_country_given_should_only_return_its_hosts() {
+        let test_cache = test_cache();
+
+        let actual_result = find_matches(
+            &test_cache,
+            &[Ipv4Addr::new(1, 2, 3, 4)],
+            Some(&[CountryCode::De]),
+        );
+
+        assert_eq!(actual_result.len(), 1);
+        assert_eq!(actual_result[0].1.num, 7);
+    }
+}
+
+#[cfg(test)]
+mod sweep_queue_tests {
+    use super::*;
+
+    fn test_job(num: u8) -> SweepJob {
+        SweepJob::new(vec![CountryCode::Au], vec![Ipv4Addr::new(1, 2, 3, num)])
+    }
+
+    #[test]
+    fn when_same_sweep_pending_should_queue_it_once() {
+        let mut test_queue = SweepQueue::default();
+        test_queue.push(test_job(1));
+        test_queue.running = Some(test_job(2).key);
+
+        let actual_result = test_queue.push(test_job(1)) && test_queue.push(test_job(2));
+
+        assert!(actual_result);
+        assert_eq!(test_queue.queued.len(), 1);
+    }
+
+    #[test]
+    fn when_queue_full_should_turn_sweep_away() {
+        let mut test_queue = SweepQueue::default();
+        for num in 0..MAX_QUEUED_SWEEPS as u8 {
+            test_queue.push(test_job(num));
+        }
+
+        let actual_result = test_queue.push(test_job(200));
+
+        assert!(!actual_result);
+        assert!(!test_queue.is_pending(&test_job(200).key));
+    }
+}
@@ -0,0 +1,389 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::dns_resolver::DnsResolver;
+use super::enum_country_code::CountryCode;
+use super::enum_match_type::MatchType;
+use super::export::write_csv;
+use super::host_cache::{unix_now, HostCache};
+use super::interrupt::Interrupt;
+use super::output::Output;
+use super::progress::{format_match, IndicatifProgress};
+use super::scan::Scan;
+use super::worker_models::{Concurrency, LookupPolicy, SearchFilter, WorkerResponse};
+use super::{build_response, is_in_same_subnet, is_valid_ip, parse_country, parse_country_list};
+use console::Term;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+use std::net::Ipv4Addr;
+
+// Hostnames either side of the one asked about with `neighbors`
+const NEIGHBOR_RADIUS: u32 = 5;
+
+const HELP: &str = "Commands:
+  find <ip>[,<ip>...]      Search the current countries for hostnames resolving to an ip
+  country <cc>[,<cc>...]   Switch countries (or `all`), sweeping any not in memory yet
+  neighbors <host>         Hostnames numbered next to a host & hosts in its /24, E.g. neighbors au512
+  refresh                  Sweep the current countries again, retrying timeouts
+  export csv [path]        Save every resolved hostname in memory
+  help                     Show this message
+  quit                     Leave the shell";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Find(Vec<Ipv4Addr>),
+    Country(Vec<String>),
+    Neighbors(String, u32),
+    Refresh,
+    Export(Option<String>),
+    Help,
+    Quit,
+}
+
+// Lookups of one country kept across queries
+#[derive(Default)]
+struct HostTable {
+    rows: Vec<WorkerResponse>,
+    scanned: HashSet<u32>,
+}
+
+struct Shell {
+    countries: Vec<CountryCode>,
+    numbers: Vec<u32>,
+    concurrency: Concurrency,
+    resolver: DnsResolver,
+    policy: LookupPolicy,
+    cache_path: Option<String>,
+    interrupt: Interrupt, // stops the sweep in progress, not the shell
+    tables: HashMap<String, HostTable>,
+}
+
+impl Shell {
+    // Resolve whatever of a country isn't in memory yet, later queries only read the table
+    fn sweep(&mut self, country_code: &CountryCode, refresh: bool) -> Result<(), String> {
+        let key = country_code.to_string();
+        if refresh {
+            self.tables.remove(&key);
+        }
+        let table = self.tables.entry(key).or_default();
+        let pending: Vec<u32> = self
+            .numbers
+            .iter()
+            .filter(|num| !table.scanned.contains(num))
+            .cloned()
+            .collect();
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        println!(
+            "🔭 Sweeping {} ({} hostnames)...",
+            country_code,
+            pending.len()
+        );
+
+        // the table is matched per query, so the sweep itself looks for nothing
+        let mut scan = Scan::builder()
+            .country(country_code.clone())
+            .numbers(pending)
+            .collect_table(true)
+            .concurrency(self.concurrency)
+            .resolver(self.resolver.clone())
+            .policy(self.policy.clone())
+            .output(Output::Silent)
+            .progress(IndicatifProgress::default())
+            .interrupt(&self.interrupt);
+        if let Some(cache) = self
+            .cache_path
+            .as_ref()
+            .and_then(|path| HostCache::load(path).ok())
+        {
+            scan = scan.cache(cache);
+        }
+
+        let results = scan.build()?.run()?;
+        if results.interrupted {
+            println!("⏹  Interrupted, the rest is swept by the next query");
+        }
+        if let Some(err) = &results.cache_error {
+            eprintln!("warning: {}", err);
+        }
+        // timeouts & errors are kept as they are, `refresh` gives them another try
+        for item in results.responses.into_iter() {
+            table.scanned.insert(item.num);
+            table.rows.push(item);
+        }
+        Ok(())
+    }
+
+    // Every row of the current countries matched against the targets
+    fn find(&mut self, targets: Vec<Ipv4Addr>) -> Result<(), String> {
+        for country_code in self.countries.clone().iter() {
+            self.sweep(country_code, false)?;
+        }
+
+        let filter = SearchFilter::new(targets);
+        let mut matches: Vec<WorkerResponse> = self
+            .countries
+            .iter()
+            .filter_map(|country_code| self.tables.get(&country_code.to_string()))
+            .flat_map(|table| table.rows.iter())
+            .map(|item| build_response(&item.country_code, item.num, item.outcome.clone(), &filter))
+            .filter(|item| item.ip.is_some())
+            .collect();
+        matches.sort_by_key(|item| matches!(item.match_type, MatchType::Partial));
+
+        if matches.is_empty() {
+            println!("No match");
+        }
+        for item in matches.iter() {
+            if let Some(ip) = item.ip {
+                println!("{}", format_match(item, &ip));
+            }
+        }
+        Ok(())
+    }
+
+    fn neighbors(&mut self, country: &str, num: u32) -> Result<(), String> {
+        let country_code = parse_country(country)?;
+        self.sweep(&country_code, false)?;
+
+        let table = &self.tables[&country_code.to_string()];
+        let host = match table.rows.iter().find(|item| item.num == num) {
+            Some(host) => host,
+            None => return Err(format!("{}{} is outside the scanned range", country, num)),
+        };
+        println!("{}: {}", host.host, host.outcome);
+
+        println!("\nNumbered next to it:");
+        let mut nearby: Vec<&WorkerResponse> = table
+            .rows
+            .iter()
+            .filter(|item| item.num != num && item.num.abs_diff(num) <= NEIGHBOR_RADIUS)
+            .collect();
+        nearby.sort_by_key(|item| item.num);
+        for item in nearby.iter() {
+            println!(" - {}: {}", item.host, item.outcome);
+        }
+
+        println!("\nIn the same /24:");
+        let addresses = host.outcome.addresses();
+        let mut subnet: Vec<&WorkerResponse> = table
+            .rows
+            .iter()
+            .filter(|item| item.num != num)
+            .filter(|item| {
+                item.outcome
+                    .addresses()
+                    .iter()
+                    .any(|ip| addresses.iter().any(|own| is_in_same_subnet(ip, own)))
+            })
+            .collect();
+        subnet.sort_by_key(|item| item.num);
+        if subnet.is_empty() {
+            println!(" - none");
+        }
+        for item in subnet.iter() {
+            println!(" - {}: {}", item.host, item.outcome);
+        }
+        Ok(())
+    }
+
+    fn export(&self, path: Option<String>) -> Result<(), String> {
+        let path = path.unwrap_or_else(|| format!("fns-shell-{}.csv", unix_now()));
+        let mut rows: Vec<WorkerResponse> = self
+            .tables
+            .values()
+            .flat_map(|table| table.rows.iter().cloned())
+            .collect();
+        rows.sort_by(|a, b| {
+            (a.country_code.to_string(), a.num).cmp(&(b.country_code.to_string(), b.num))
+        });
+
+        let count = write_csv(&path, &rows)?;
+        println!("💾 Exported {} rows to {}", count, path);
+        Ok(())
+    }
+
+    // false once the user quits
+    fn execute(&mut self, command: Command) -> Result<bool, String> {
+        match command {
+            Command::Find(targets) => self.find(targets)?,
+            Command::Country(countries) => {
+                self.countries = parse_country_list(&countries.join(","))?;
+                for country_code in self.countries.clone().iter() {
+                    self.sweep(country_code, false)?;
+                }
+            }
+            Command::Neighbors(country, num) => self.neighbors(&country, num)?,
+            Command::Refresh => {
+                for country_code in self.countries.clone().iter() {
+                    self.sweep(country_code, true)?;
+                }
+            }
+            Command::Export(path) => self.export(path)?,
+            Command::Help => println!("{}", HELP),
+            Command::Quit => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn prompt(&self) -> String {
+        let countries: Vec<String> = self.countries.iter().map(|item| item.to_string()).collect();
+        format!("fns [{}]> ", countries.join(","))
+    }
+}
+
+// Util: split a hostname into its country & server number, E.g. au512 or au512.nordvpn.com
+fn parse_host(value: &str) -> Result<(String, u32), String> {
+    let host = value.trim().trim_end_matches(".nordvpn.com");
+    let split = host
+        .find(|c: char| c.is_ascii_digit())
+        .ok_or_else(|| format!("Expected a hostname like au512, got: {}", value))?;
+    let num = host[split..]
+        .parse::<u32>()
+        .map_err(|_| format!("Expected a hostname like au512, got: {}", value))?;
+    Ok((host[..split].to_string(), num))
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or_default().to_lowercase();
+    let args: Vec<&str> = words.collect();
+    let list = |args: &[&str]| -> Vec<String> {
+        args.join(",")
+            .split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    };
+
+    match (name.as_str(), args.as_slice()) {
+        ("find", args) if !args.is_empty() => {
+            let mut targets = Vec::new();
+            for item in list(args).into_iter() {
+                is_valid_ip(item.clone()).map_err(|err| format!("{}: {}", err, item))?;
+                targets.push(item.parse::<Ipv4Addr>().unwrap());
+            }
+            Ok(Command::Find(targets))
+        }
+        ("country", args) if !args.is_empty() => Ok(Command::Country(list(args))),
+        ("neighbors", [host]) => {
+            let (country, num) = parse_host(host)?;
+            Ok(Command::Neighbors(country, num))
+        }
+        ("refresh", []) => Ok(Command::Refresh),
+        ("export", ["csv"]) => Ok(Command::Export(None)),
+        ("export", ["csv", path]) => Ok(Command::Export(Some(path.to_string()))),
+        ("export", _) => Err(String::from("Usage: export csv [path]")),
+        ("help", _) => Ok(Command::Help),
+        ("quit", _) | ("exit", _) => Ok(Command::Quit),
+        _ => Err(format!("Unknown command: {} (try help)", line.trim())),
+    }
+}
+
+// --Entry Point--
+// - reads commands from stdin, so a script can be piped in as well
+pub fn entry_point_shell(
+    countries: Vec<CountryCode>,
+    numbers: Vec<u32>,
+    concurrency: Concurrency,
+    resolver: DnsResolver,
+    policy: LookupPolicy,
+    cache_path: Option<String>,
+    interrupt: Interrupt,
+) -> Result<(), String> {
+    if countries.is_empty() {
+        return Err(String::from("At least one Country Code is required"));
+    }
+    if numbers.is_empty() {
+        return Err(String::from("No server numbers to scan"));
+    }
+
+    let mut shell = Shell {
+        countries,
+        numbers,
+        concurrency,
+        resolver,
+        policy,
+        cache_path,
+        interrupt,
+        tables: HashMap::new(),
+    };
+
+    let interactive = Term::stdout().is_term();
+    if interactive {
+        println!("fns shell, the first query sweeps a country & later ones are instant. Type help for commands\n");
+    }
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        if interactive {
+            print!("{}", shell.prompt());
+            let _ = io::stdout().flush();
+        }
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match parse_command(&line).and_then(|command| shell.execute(command)) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => eprintln!("error: {}", err),
+        }
+    }
+    Ok(())
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod parse_command_tests {
+    use super::*;
+
+    #[test]
+    fn when_find_with_ips_should_return_targets() {
+        let actual_result = parse_command("find 1.2.3.4, 5.6.7.8").unwrap();
+
+        assert_eq!(
+            actual_result,
+            Command::Find(vec![Ipv4Addr::new(1, 2, 3, 4), Ipv4Addr::new(5, 6, 7, 8)])
+        );
+    }
+
+    #[test]
+    fn when_neighbors_should_split_hostname() {
+        assert_eq!(
+            parse_command("neighbors au512").unwrap(),
+            Command::Neighbors(String::from("au"), 512)
+        );
+        assert_eq!(
+            parse_command("neighbors us42.nordvpn.com").unwrap(),
+            Command::Neighbors(String::from("us"), 42)
+        );
+        assert!(parse_command("neighbors au").is_err());
+    }
+
+    #[test]
+    fn when_export_should_only_accept_csv() {
+        assert_eq!(parse_command("export csv").unwrap(), Command::Export(None));
+        assert_eq!(
+            parse_command("export csv out.csv").unwrap(),
+            Command::Export(Some(String::from("out.csv")))
+        );
+        assert!(parse_command("export json").is_err());
+    }
+
+    #[test]
+    fn when_unknown_or_invalid_should_return_error() {
+        assert!(parse_command("lookup 1.2.3.4").is_err());
+        assert!(parse_command("find 127.0.0.1").is_err());
+    }
+}
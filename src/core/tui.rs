@@ -0,0 +1,598 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::dns_resolver::DnsResolver;
+use super::enum_country_code::CountryCode;
+use super::enum_lookup_outcome::{LookupOutcome, LookupSummary};
+use super::enum_match_type::MatchType;
+use super::export::write_csv;
+use super::host_cache::{unix_now, HostCache};
+use super::interrupt::Interrupt;
+use super::progress::ScanProgress;
+use super::scan::{Scan, ScanEvent, ScanHandle, ScanResults};
+use super::worker_models::{Concurrency, LookupPolicy, SearchFilter, WorkerResponse};
+use super::{build_response, is_valid_ip};
+use console::{style, truncate_str, Key, Term};
+use std::collections::HashSet;
+use std::net::Ipv4Addr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often the screen is redrawn while no key is pressed
+const FRAME: Duration = Duration::from_millis(100);
+
+// Lines taken by the title, stats, workers & table header above the table, and the footer below it
+const HEADER_LINES: usize = 5;
+const FOOTER_LINES: usize = 3;
+
+#[derive(Clone, Default)]
+struct WorkerStatus {
+    load: usize,
+    scanned: usize,
+    last: String,
+}
+
+// Worker status for the status line, fed by the engine through ScanProgress
+#[derive(Clone, Default)]
+struct TuiProgress {
+    workers: Arc<Mutex<Vec<WorkerStatus>>>,
+}
+
+impl ScanProgress for TuiProgress {
+    fn started(&self, worker_loads: &[usize]) {
+        *self.workers.lock().unwrap() = worker_loads
+            .iter()
+            .map(|load| WorkerStatus {
+                load: *load,
+                ..WorkerStatus::default()
+            })
+            .collect();
+    }
+
+    fn host_scanned(&self, worker: usize, hostname: &str) {
+        if let Some(status) = self.workers.lock().unwrap().get_mut(worker) {
+            status.scanned += 1;
+            status.last = hostname.trim_end_matches(".nordvpn.com").to_string();
+        }
+    }
+
+    fn matched(&self, _response: &WorkerResponse) {}
+
+    fn finished(&self, _summary: &LookupSummary) {}
+}
+
+enum ScanState {
+    Running,
+    Cancelling,
+    Cancelled,
+    Finished,
+    Failed(String),
+}
+
+enum Mode {
+    Normal,
+    EditTarget(String),
+}
+
+// Alternate screen with a hidden cursor, restored however the TUI is left (including a panic)
+struct AlternateScreen<'a> {
+    term: &'a Term,
+}
+
+impl<'a> AlternateScreen<'a> {
+    fn enter(term: &'a Term) -> AlternateScreen<'a> {
+        let _ = term.write_str("\x1b[?1049h");
+        let _ = term.hide_cursor();
+        AlternateScreen { term }
+    }
+}
+
+impl Drop for AlternateScreen<'_> {
+    fn drop(&mut self) {
+        let _ = self.term.show_cursor();
+        let _ = self.term.write_str("\x1b[?1049l");
+    }
+}
+
+// A single country sweep that can be paused, cancelled & re-targeted without losing what was resolved
+struct Tui {
+    country_code: CountryCode,
+    numbers: Vec<u32>,
+    concurrency: Concurrency,
+    resolver: DnsResolver,
+    policy: LookupPolicy,
+    cache_path: Option<String>,
+    target: Ipv4Addr,
+    rows: Vec<WorkerResponse>, // every lookup, in the order they completed
+    scanned: HashSet<u32>,
+    handle: Option<ScanHandle>,
+    progress: TuiProgress,
+    state: ScanState,
+    mode: Mode,
+    message: String,
+    stop_watch: Instant,
+}
+
+impl Tui {
+    // Scan the numbers not resolved yet for the current target
+    fn start_scan(&mut self) {
+        let pending: Vec<u32> = self
+            .numbers
+            .iter()
+            .filter(|num| !self.scanned.contains(num))
+            .cloned()
+            .collect();
+        if pending.is_empty() {
+            self.state = ScanState::Finished;
+            return;
+        }
+
+        self.progress = TuiProgress::default();
+        let mut scan = Scan::builder()
+            .country(self.country_code.clone())
+            .numbers(pending)
+            .target(self.target)
+            .first(false)
+            .concurrency(self.concurrency)
+            .resolver(self.resolver.clone())
+            .policy(self.policy.clone())
+            .progress(self.progress.clone());
+        if let Some(cache) = self
+            .cache_path
+            .as_ref()
+            .and_then(|path| HostCache::load(path).ok())
+        {
+            scan = scan.cache(cache);
+        }
+
+        match scan.build() {
+            Ok(scan) => {
+                self.handle = Some(scan.start());
+                self.state = ScanState::Running;
+            }
+            Err(err) => self.state = ScanState::Failed(err),
+        }
+    }
+
+    fn apply(&mut self, event: ScanEvent) {
+        match event {
+            ScanEvent::Match(item) | ScanEvent::LookupDone(item) | ScanEvent::Error(item) => {
+                self.scanned.insert(item.num);
+                self.rows.push(item);
+            }
+            ScanEvent::Finished(Ok(results)) => {
+                self.handle = None;
+                self.state = if results.interrupted {
+                    ScanState::Cancelled
+                } else {
+                    ScanState::Finished
+                };
+            }
+            ScanEvent::Finished(Err(err)) => {
+                self.handle = None;
+                self.state = ScanState::Failed(err);
+            }
+        }
+    }
+
+    // Re-match everything resolved so far against the new target, then scan the rest
+    fn retarget(&mut self, target: Ipv4Addr) {
+        if let Some(handle) = self.handle.take() {
+            handle.cancel();
+        }
+
+        self.target = target;
+        let filter = SearchFilter::new(vec![target]);
+        self.rows = self
+            .rows
+            .drain(..)
+            .map(|item| build_response(&item.country_code, item.num, item.outcome, &filter))
+            .collect();
+        self.message = format!("🎯 Target changed to {}", target);
+        self.start_scan();
+    }
+
+    fn export(&mut self) {
+        let path = format!("fns-{}-{}.csv", self.country_code, unix_now());
+        self.message = match write_csv(&path, &self.rows) {
+            Ok(count) => format!("💾 Exported {} rows to {}", count, path),
+            Err(err) => format!("⚠️  {}", err),
+        };
+    }
+
+    // false once the user quits
+    fn on_key(&mut self, key: Key) -> bool {
+        if matches!(key, Key::Char('q') | Key::CtrlC) {
+            return false;
+        }
+
+        match &mut self.mode {
+            Mode::EditTarget(input) => match key {
+                Key::Char(c) if c.is_ascii_digit() || c == '.' => input.push(c),
+                Key::Backspace => {
+                    input.pop();
+                }
+                Key::Escape => self.mode = Mode::Normal,
+                Key::Enter => {
+                    let input = input.clone();
+                    match is_valid_ip(input.clone()).and_then(|_| {
+                        input
+                            .parse::<Ipv4Addr>()
+                            .map_err(|_| String::from("Invalid IP Address"))
+                    }) {
+                        Ok(target) => {
+                            self.mode = Mode::Normal;
+                            self.retarget(target);
+                        }
+                        Err(err) => self.message = format!("⚠️  {}: {}", err, input),
+                    }
+                }
+                _ => {}
+            },
+            Mode::Normal => match key {
+                Key::Char('p') => {
+                    if let Some(handle) = &self.handle {
+                        if handle.is_paused() {
+                            handle.resume();
+                        } else {
+                            handle.pause();
+                        }
+                    }
+                }
+                Key::Char('c') => {
+                    if let Some(handle) = &self.handle {
+                        handle.resume();
+                        handle.cancel();
+                        self.state = ScanState::Cancelling;
+                    }
+                }
+                Key::Char('t') => self.mode = Mode::EditTarget(String::new()),
+                Key::Char('e') => self.export(),
+                _ => {}
+            },
+        }
+        true
+    }
+
+    fn state_badge(&self) -> String {
+        let paused = self
+            .handle
+            .as_ref()
+            .is_some_and(|handle| handle.is_paused());
+        match &self.state {
+            ScanState::Running if paused => style("⏸ paused").yellow().to_string(),
+            ScanState::Running => style("🔎 scanning").cyan().to_string(),
+            ScanState::Cancelling => style("⏹ cancelling").yellow().to_string(),
+            ScanState::Cancelled => style("⏹ cancelled").yellow().to_string(),
+            ScanState::Finished => style("✨ finished").green().to_string(),
+            ScanState::Failed(err) => style(format!("💥 {}", err)).red().to_string(),
+        }
+    }
+
+    fn render(&self, height: usize, width: usize) -> Vec<String> {
+        let mut summary = LookupSummary::default();
+        for item in self.rows.iter() {
+            summary.add(&item.outcome);
+        }
+        let exact = self
+            .rows
+            .iter()
+            .filter(|item| matches!(item.match_type, MatchType::Exact))
+            .count();
+        let partial = self
+            .rows
+            .iter()
+            .filter(|item| matches!(item.match_type, MatchType::Partial))
+            .count();
+        let elapsed = self.stop_watch.elapsed();
+
+        let mut lines = vec![
+            format!(
+                "{} {} {}-{} → {}   {}",
+                style("fns tui").bold(),
+                self.country_code,
+                self.numbers.first().cloned().unwrap_or_default(),
+                self.numbers.last().cloned().unwrap_or_default(),
+                style(self.target).bold(),
+                self.state_badge()
+            ),
+            truncate_str(
+                &format!(
+                    "{}/{} hostnames   {:.1}/s   {} exact   {} partial   {} timeouts & errors   {:02}:{:02}",
+                    self.scanned.len(),
+                    self.numbers.len(),
+                    self.rows.len() as f64 / elapsed.as_secs_f64().max(0.001),
+                    exact,
+                    partial,
+                    summary.timeout + summary.error,
+                    elapsed.as_secs() / 60,
+                    elapsed.as_secs() % 60
+                ),
+                width,
+                "…",
+            )
+            .to_string(),
+            truncate_str(&self.worker_line(), width, "…").to_string(),
+            String::new(),
+            style(format!("{:<22} {:<9} {}", "HOST", "MATCH", "ADDRESSES"))
+                .underlined()
+                .to_string(),
+        ];
+
+        // matches stay on screen, the rest of the table shows the latest lookups
+        let table_height = height.saturating_sub(HEADER_LINES + FOOTER_LINES);
+        let mut table: Vec<&WorkerResponse> = self
+            .rows
+            .iter()
+            .filter(|item| matches!(item.match_type, MatchType::Exact))
+            .chain(
+                self.rows
+                    .iter()
+                    .filter(|item| matches!(item.match_type, MatchType::Partial)),
+            )
+            .collect();
+        table.extend(
+            self.rows
+                .iter()
+                .rev()
+                .filter(|item| matches!(item.match_type, MatchType::None)),
+        );
+        for item in table.into_iter().take(table_height) {
+            lines.push(format_row(item, width));
+        }
+        while lines.len() < height.saturating_sub(FOOTER_LINES) {
+            lines.push(String::new());
+        }
+
+        lines.push(String::new());
+        lines.push(truncate_str(&self.message, width, "…").to_string());
+        lines.push(match &self.mode {
+            Mode::Normal => style(
+                "[p] pause/resume   [c] cancel   [t] change target   [e] export csv   [q] quit",
+            )
+            .dim()
+            .to_string(),
+            Mode::EditTarget(input) => format!(
+                "New target IP: {}_   {}",
+                input,
+                style("[enter] apply   [esc] back").dim()
+            ),
+        });
+        lines
+    }
+
+    fn worker_line(&self) -> String {
+        let workers = self.progress.workers.lock().unwrap();
+        let statuses: Vec<String> = workers
+            .iter()
+            .enumerate()
+            .map(|(idx, status)| {
+                if status.scanned >= status.load {
+                    format!("#{} done", idx + 1)
+                } else {
+                    format!(
+                        "#{} {} {}/{}",
+                        idx + 1,
+                        status.last,
+                        status.scanned,
+                        status.load
+                    )
+                }
+            })
+            .collect();
+        format!("workers: {}", statuses.join("   "))
+    }
+}
+
+// Info: one table row, coloured by how well it matches
+fn format_row(item: &WorkerResponse, width: usize) -> String {
+    let details = match &item.outcome {
+        LookupOutcome::Resolved(host) => host.to_string(),
+        other => other.to_string(),
+    };
+    let line = format!(
+        "{:<22} {:<9} {}",
+        item.host,
+        item.match_type.label(),
+        details
+    );
+    let line = truncate_str(&line, width, "…");
+
+    match (&item.match_type, &item.outcome) {
+        (MatchType::Exact, _) => style(line).green().bold().to_string(),
+        (MatchType::Partial, _) => style(line).yellow().to_string(),
+        (_, LookupOutcome::Timeout) | (_, LookupOutcome::Error(_)) => style(line).red().to_string(),
+        (_, LookupOutcome::NxDomain) => style(line).dim().to_string(),
+        _ => line.to_string(),
+    }
+}
+
+// --Entry Point--
+// - returns every lookup made, E.g. for the exit code
+#[allow(clippy::too_many_arguments)]
+pub fn entry_point_tui(
+    country_code: CountryCode,
+    numbers: Vec<u32>,
+    target: Ipv4Addr,
+    concurrency: Concurrency,
+    resolver: DnsResolver,
+    policy: LookupPolicy,
+    cache_path: Option<String>,
+    interrupt: Interrupt,
+) -> Result<ScanResults, String> {
+    let term = Term::stdout();
+    if !term.is_term() {
+        return Err(String::from(
+            "fns tui needs a terminal, use --porcelain for scripts",
+        ));
+    }
+    if numbers.is_empty() {
+        return Err(String::from("No server numbers to scan"));
+    }
+
+    let mut tui = Tui {
+        country_code,
+        numbers,
+        concurrency,
+        resolver,
+        policy,
+        cache_path,
+        target,
+        rows: Vec::new(),
+        scanned: HashSet::new(),
+        handle: None,
+        progress: TuiProgress::default(),
+        state: ScanState::Running,
+        mode: Mode::Normal,
+        message: String::new(),
+        stop_watch: Instant::now(),
+    };
+    tui.start_scan();
+
+    // keys are read on their own thread, which stops after quitting so the terminal leaves raw mode
+    let (key_tx, key_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let term = Term::stdout();
+        while let Ok(key) = term.read_key() {
+            let quit = matches!(key, Key::Char('q') | Key::CtrlC);
+            if key_tx.send(key).is_err() || quit {
+                break;
+            }
+        }
+    });
+
+    // alternate screen, so the shell's scrollback is left as it was
+    let screen = AlternateScreen::enter(&term);
+
+    // Ctrl-C between two key reads arrives as a signal, it quits like the key does
+    let mut ctrl_c = interrupt.listen();
+
+    loop {
+        while let Some(event) = tui
+            .handle
+            .as_ref()
+            .and_then(|handle| handle.events().try_recv().ok())
+        {
+            tui.apply(event);
+        }
+
+        let (height, width) = term.size();
+        for (y, line) in tui
+            .render(height as usize, width as usize)
+            .iter()
+            .enumerate()
+        {
+            let _ = term.move_cursor_to(0, y);
+            let _ = term.clear_line();
+            let _ = term.write_str(line);
+        }
+        let _ = term.flush();
+
+        if ctrl_c.is_triggered() && !tui.on_key(Key::CtrlC) {
+            break;
+        }
+        match key_rx.recv_timeout(FRAME) {
+            Ok(key) => {
+                if !tui.on_key(key) {
+                    break;
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    if let Some(handle) = tui.handle.take() {
+        handle.cancel();
+    }
+    drop(screen);
+
+    // quitting before every number was looked up leaves the sweep interrupted
+    let mut summary = LookupSummary::default();
+    for item in tui.rows.iter() {
+        summary.add(&item.outcome);
+    }
+    Ok(ScanResults {
+        interrupted: tui.scanned.len() < tui.numbers.len(),
+        responses: tui.rows,
+        summary,
+        cache_error: None,
+    })
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod tui_tests {
+    use super::*;
+    use crate::core::enum_lookup_outcome::ResolvedHost;
+
+    fn test_tui(rows: Vec<WorkerResponse>) -> Tui {
+        Tui {
+            country_code: CountryCode::Au,
+            numbers: rows.iter().map(|item| item.num).collect(),
+            concurrency: Concurrency::Fixed(1),
+            resolver: DnsResolver::from_address("127.0.0.1").unwrap(),
+            policy: LookupPolicy::new(Duration::from_secs(1), 0),
+            cache_path: None,
+            target: Ipv4Addr::new(10, 0, 0, 1),
+            scanned: rows.iter().map(|item| item.num).collect(),
+            rows,
+            handle: None,
+            progress: TuiProgress::default(),
+            state: ScanState::Finished,
+            mode: Mode::Normal,
+            message: String::new(),
+            stop_watch: Instant::now(),
+        }
+    }
+
+    fn resolved(num: u32, ip: Ipv4Addr, target: Ipv4Addr) -> WorkerResponse {
+        let outcome = LookupOutcome::Resolved(ResolvedHost {
+            addresses: vec![ip],
+            cnames: Vec::new(),
+        });
+        build_response(
+            &CountryCode::Au,
+            num,
+            outcome,
+            &SearchFilter::new(vec![target]),
+        )
+    }
+
+    #[test]
+    fn when_target_changed_should_rematch_resolved_rows() {
+        let old_target = Ipv4Addr::new(10, 0, 0, 1);
+        let new_target = Ipv4Addr::new(10, 0, 0, 2);
+        let mut test_tui = test_tui(vec![
+            resolved(1, old_target, old_target),
+            resolved(2, new_target, old_target),
+        ]);
+
+        test_tui.retarget(new_target);
+
+        assert!(matches!(test_tui.rows[0].match_type, MatchType::Partial));
+        assert!(matches!(test_tui.rows[1].match_type, MatchType::Exact));
+        assert!(test_tui.handle.is_none()); // nothing left to scan
+    }
+
+    #[test]
+    fn when_editing_target_should_only_accept_ip_characters() {
+        let mut test_tui = test_tui(Vec::new());
+
+        for key in [
+            Key::Char('t'),
+            Key::Char('1'),
+            Key::Char('x'),
+            Key::Char('.'),
+        ] {
+            assert!(test_tui.on_key(key));
+        }
+
+        assert!(matches!(&test_tui.mode, Mode::EditTarget(input) if input == "1."));
+        assert!(!test_tui.on_key(Key::Char('q')));
+    }
+}
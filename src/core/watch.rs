@@ -0,0 +1,640 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+use super::dns_resolver::DnsResolver;
+use super::enum_country_code::CountryCode;
+use super::enum_lookup_outcome::LookupOutcome;
+use super::export::json_string;
+use super::get_vpn_string;
+use super::host_cache::{unix_now, HostCache};
+use super::interrupt::Interrupt;
+use super::output::Output;
+use super::progress::{IndicatifProgress, NoProgress};
+use super::scan::{Scan, ScanResults};
+use super::worker_models::{Concurrency, LookupPolicy};
+use indicatif::HumanDuration;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+// Give up on a webhook that doesn't answer, so it can't hold up the next sweep
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Addresses of every live hostname, E.g. ("au", 42) -> [1.2.3.4]
+// - None for one that has only ever timed out or failed, so it's unknown rather than absent
+type Fleet = BTreeMap<(String, u32), Option<Vec<Ipv4Addr>>>;
+
+// A hostname that changed between two sweeps
+#[derive(Debug, PartialEq)]
+enum FleetChange {
+    Appeared(String, Vec<Ipv4Addr>),
+    Disappeared(String, Vec<Ipv4Addr>),
+    Changed(String, Vec<Ipv4Addr>, Vec<Ipv4Addr>),
+}
+
+impl FleetChange {
+    fn describe(&self) -> String {
+        match self {
+            FleetChange::Appeared(host, addresses) => format!(" + {}: {}", host, join(addresses)),
+            FleetChange::Disappeared(host, addresses) => {
+                format!(" - {}: {}", host, join(addresses))
+            }
+            FleetChange::Changed(host, before, after) => {
+                format!(" ~ {}: {} -> {}", host, join(before), join(after))
+            }
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let addresses = |addresses: &[Ipv4Addr]| -> String {
+            let items: Vec<String> = addresses
+                .iter()
+                .map(|ip| json_string(&ip.to_string()))
+                .collect();
+            format!("[{}]", items.join(","))
+        };
+        match self {
+            FleetChange::Appeared(host, after) => format!(
+                "{{\"type\":\"appeared\",\"host\":{},\"before\":[],\"after\":{}}}",
+                json_string(host),
+                addresses(after)
+            ),
+            FleetChange::Disappeared(host, before) => format!(
+                "{{\"type\":\"disappeared\",\"host\":{},\"before\":{},\"after\":[]}}",
+                json_string(host),
+                addresses(before)
+            ),
+            FleetChange::Changed(host, before, after) => format!(
+                "{{\"type\":\"changed\",\"host\":{},\"before\":{},\"after\":{}}}",
+                json_string(host),
+                addresses(before),
+                addresses(after)
+            ),
+        }
+    }
+}
+
+// Util: the fleet after a sweep
+// - NXDOMAIN takes a hostname out, timeouts & errors keep what was known about it (if anything)
+fn next_fleet(fleet: &Fleet, results: &ScanResults) -> Fleet {
+    let mut next = fleet.clone();
+    for item in results.responses.iter() {
+        let key = (item.country_code.to_string(), item.num);
+        match &item.outcome {
+            LookupOutcome::Resolved(host) => {
+                let mut addresses = host.addresses.clone();
+                addresses.sort();
+                next.insert(key, Some(addresses));
+            }
+            LookupOutcome::NxDomain => {
+                next.remove(&key);
+            }
+            LookupOutcome::Timeout | LookupOutcome::Error(_) => {
+                next.entry(key).or_insert(None);
+            }
+        }
+    }
+    next
+}
+
+// Util: hostnames with known addresses
+fn live_count(fleet: &Fleet) -> usize {
+    fleet
+        .values()
+        .filter(|addresses| addresses.is_some())
+        .count()
+}
+
+// Util: what changed from one fleet to the next, by country & server number
+// - a hostname unknown on either side isn't compared
+fn diff_fleet(before: &Fleet, after: &Fleet) -> Vec<FleetChange> {
+    let host = |(country, num): &(String, u32)| {
+        get_vpn_string(&country.parse().unwrap_or(CountryCode::Empty), num)
+    };
+    let mut changes = Vec::new();
+
+    for (key, addresses) in after.iter() {
+        let addresses = match addresses {
+            Some(addresses) => addresses,
+            None => continue,
+        };
+        match before.get(key) {
+            None => changes.push((key, FleetChange::Appeared(host(key), addresses.clone()))),
+            Some(Some(previous)) if previous != addresses => changes.push((
+                key,
+                FleetChange::Changed(host(key), previous.clone(), addresses.clone()),
+            )),
+            Some(_) => {}
+        }
+    }
+    for (key, addresses) in before.iter().filter(|(key, _)| !after.contains_key(*key)) {
+        if let Some(addresses) = addresses {
+            changes.push((key, FleetChange::Disappeared(host(key), addresses.clone())));
+        }
+    }
+
+    changes.sort_by(|a, b| a.0.cmp(b.0));
+    changes.into_iter().map(|(_, change)| change).collect()
+}
+
+// Util: an interval like 1h, 30m, 90s or 1h30m, plain numbers are seconds
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid interval: {}, E.g. 1h, 30m or 90s", value);
+    let value = value.trim();
+
+    // a plain number is seconds
+    let seconds = match value.parse::<u64>() {
+        Ok(seconds) => seconds,
+        Err(_) => {
+            let mut seconds = 0;
+            let mut digits = String::new();
+            for c in value.chars() {
+                if c.is_ascii_digit() {
+                    digits.push(c);
+                    continue;
+                }
+                let unit = match c {
+                    'h' => 3600,
+                    'm' => 60,
+                    's' => 1,
+                    _ => return Err(invalid()),
+                };
+                seconds += digits.parse::<u64>().map_err(|_| invalid())? * unit;
+                digits.clear();
+            }
+            if !digits.is_empty() {
+                return Err(invalid());
+            }
+            seconds
+        }
+    };
+    if seconds == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+// Util: split http://host:port/path into the address to connect to, the Host header & the path
+// - only plain http, E.g. a local relay, there is no TLS to speak https with
+fn parse_webhook_url(url: &str) -> Result<(String, String, String), String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("Only http:// webhooks are supported, got: {}", url))?;
+    let (host, path) = match rest.find('/') {
+        Some(split) => (&rest[..split], &rest[split..]),
+        None => (rest, "/"),
+    };
+    if host.is_empty() {
+        return Err(format!("Invalid webhook url: {}", url));
+    }
+    let address = match host.contains(':') {
+        true => host.to_string(),
+        false => format!("{}:80", host),
+    };
+    Ok((address, host.to_string(), path.to_string()))
+}
+
+// Util: the first address of the webhook's host that connects within WEBHOOK_TIMEOUT
+fn connect_webhook(address: &str) -> std::io::Result<TcpStream> {
+    let mut last_err = None;
+    for addr in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, WEBHOOK_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "no address to connect to")
+    }))
+}
+
+// POST the changes of a round as JSON, a failed delivery is only reported
+fn post_webhook(
+    url: &str,
+    countries: &[CountryCode],
+    changes: &[FleetChange],
+) -> Result<(), String> {
+    let (address, host, path) = parse_webhook_url(url)?;
+    let countries: Vec<String> = countries
+        .iter()
+        .map(|item| json_string(&item.to_string()))
+        .collect();
+    let changes: Vec<String> = changes.iter().map(FleetChange::to_json).collect();
+    let body = format!(
+        "{{\"countries\":[{}],\"checked\":{},\"changes\":[{}]}}",
+        countries.join(","),
+        unix_now(),
+        changes.join(",")
+    );
+
+    let failed = |err: std::io::Error| format!("Webhook {} failed: {}", url, err);
+    let mut stream = connect_webhook(&address).map_err(failed)?;
+    stream
+        .set_read_timeout(Some(WEBHOOK_TIMEOUT))
+        .map_err(failed)?;
+    stream
+        .set_write_timeout(Some(WEBHOOK_TIMEOUT))
+        .map_err(failed)?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        host,
+        body.len(),
+        body
+    )
+    .map_err(failed)?;
+
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response);
+    let status = response.split_whitespace().nth(1).unwrap_or_default();
+    match status.starts_with('2') {
+        true => Ok(()),
+        false => Err(format!(
+            "Webhook {} answered: {}",
+            url,
+            response.lines().next().unwrap_or("nothing")
+        )),
+    }
+}
+
+fn join(addresses: &[Ipv4Addr]) -> String {
+    addresses
+        .iter()
+        .map(|ip| ip.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// --Entry Point--
+// - the first sweep is the baseline, every sweep after it reports what changed since the one before
+// - an interrupt during a sweep or while waiting for the next one stops watching, a partial sweep is never compared
+#[allow(clippy::too_many_arguments)]
+pub fn entry_point_watch(
+    countries: Vec<CountryCode>,
+    numbers: Vec<u32>,
+    interval: Duration,
+    webhook: Option<String>,
+    concurrency: Concurrency,
+    resolver: DnsResolver,
+    policy: LookupPolicy,
+    cache_path: Option<String>,
+    interrupt: Interrupt,
+) -> Result<(), String> {
+    if let Some(url) = &webhook {
+        parse_webhook_url(url)?;
+    }
+    let names: Vec<String> = countries.iter().map(|item| item.to_string()).collect();
+    println!(
+        "👀 Watching {} ({} hostnames each) every {}",
+        names.join(","),
+        numbers.len(),
+        HumanDuration(interval)
+    );
+
+    let stopped = || println!("⏹  Interrupted, stopped watching");
+    let mut stop = interrupt.listen();
+    let mut fleet: Option<Fleet> = None;
+    let mut round = 0;
+    loop {
+        round += 1;
+        let stopwatch = Instant::now();
+
+        // the table is diffed, so the sweep itself looks for nothing
+        let mut scan = Scan::builder()
+            .countries(countries.clone())
+            .numbers(numbers.clone())
+            .collect_table(true)
+            .concurrency(concurrency)
+            .resolver(resolver.clone())
+            .policy(policy.clone())
+            .output(Output::Silent)
+            .interrupt(&interrupt);
+        scan = match Output::detect() {
+            Output::Pretty => scan.progress(IndicatifProgress::default()),
+            _ => scan.progress(NoProgress),
+        };
+        if let Some(cache) = cache_path
+            .as_ref()
+            .and_then(|path| HostCache::load(path).ok())
+        {
+            scan = scan.cache(cache);
+        }
+
+        let results = scan.build()?.run()?;
+        if let Some(err) = &results.cache_error {
+            eprintln!("warning: {}", err);
+        }
+        if results.interrupted {
+            stopped();
+            return Ok(());
+        }
+
+        let previous = fleet.take().unwrap_or_default();
+        let next = next_fleet(&previous, &results);
+        let abandoned = results.summary.timeout + results.summary.error;
+        let abandoned = match abandoned {
+            0 => String::new(),
+            count => format!(", {} timed out or failed (kept as they were)", count),
+        };
+
+        if round == 1 {
+            println!(
+                "🔁 Round 1: baseline of {} live hostnames in {}{}",
+                live_count(&next),
+                HumanDuration(stopwatch.elapsed()),
+                abandoned
+            );
+        } else {
+            let changes = diff_fleet(&previous, &next);
+            println!(
+                "🔁 Round {}: {} live hostnames, {} changes{}",
+                round,
+                live_count(&next),
+                changes.len(),
+                abandoned
+            );
+            for change in changes.iter() {
+                println!("{}", change.describe());
+            }
+            if let (Some(url), false) = (&webhook, changes.is_empty()) {
+                match post_webhook(url, &countries, &changes) {
+                    Ok(_) => println!("📮 Posted {} changes to {}", changes.len(), url),
+                    Err(err) => eprintln!("warning: {}", err),
+                }
+            }
+        }
+        fleet = Some(next);
+
+        if stop.wait_timeout(interval.saturating_sub(stopwatch.elapsed())) {
+            stopped();
+            return Ok(());
+        }
+    }
+}
+
+//-----------------
+// 🧪 UNIT TESTS 🧪
+//-----------------
+#[cfg(test)]
+mod diff_fleet_tests {
+    use super::super::enum_lookup_outcome::{LookupSummary, ResolvedHost};
+    use super::super::enum_match_type::MatchType;
+    use super::super::worker_models::WorkerResponse;
+    use super::*;
+
+    fn fleet(hosts: &[(&str, u32, [u8; 4])]) -> Fleet {
+        hosts
+            .iter()
+            .map(|(country, num, ip)| {
+                ((country.to_string(), *num), Some(vec![Ipv4Addr::from(*ip)]))
+            })
+            .collect()
+    }
+
+    fn test_results(outcomes: Vec<(u32, LookupOutcome)>) -> ScanResults {
+        ScanResults {
+            responses: outcomes
+                .into_iter()
+                .map(|(num, outcome)| WorkerResponse {
+                    country_code: CountryCode::Au,
+                    num,
+                    match_type: MatchType::None,
+                    host: get_vpn_string(&CountryCode::Au, &num),
+                    ip: None,
+                    outcome,
+                })
+                .collect(),
+            summary: LookupSummary::default(),
+            interrupted: false,
+            cache_error: None,
+        }
+    }
+
+    #[test]
+    fn when_hosts_changed_should_report_each_kind() {
+        let before = fleet(&[
+            ("au", 1, [1, 1, 1, 1]),
+            ("au", 2, [2, 2, 2, 2]),
+            ("au", 3, [3, 3, 3, 3]),
+        ]);
+        let after = fleet(&[
+            ("au", 1, [1, 1, 1, 1]),
+            ("au", 2, [2, 2, 2, 9]),
+            ("au", 4, [4, 4, 4, 4]),
+        ]);
+
+        let actual_result: Vec<String> = diff_fleet(&before, &after)
+            .iter()
+            .map(FleetChange::describe)
+            .collect();
+
+        assert_eq!(
+            actual_result,
+            vec![
+                " ~ au2.nordvpn.com: 2.2.2.2 -> 2.2.2.9",
+                " - au3.nordvpn.com: 3.3.3.3",
+                " + au4.nordvpn.com: 4.4.4.4",
+            ]
+        );
+    }
+
+    #[test]
+    fn when_host_timed_out_in_baseline_should_not_report_it_appeared() {
+        let test_resolved = |ip: [u8; 4]| {
+            LookupOutcome::Resolved(ResolvedHost {
+                addresses: vec![Ipv4Addr::from(ip)],
+                cnames: Vec::new(),
+            })
+        };
+        let baseline = next_fleet(
+            &Fleet::new(),
+            &test_results(vec![
+                (1, test_resolved([1, 1, 1, 1])),
+                (2, LookupOutcome::Timeout),
+            ]),
+        );
+        let before = next_fleet(&baseline, &test_results(vec![(2, LookupOutcome::Timeout)]));
+        let after = next_fleet(
+            &before,
+            &test_results(vec![
+                (2, test_resolved([2, 2, 2, 2])),
+                (3, test_resolved([3, 3, 3, 3])),
+            ]),
+        );
+
+        let actual_result: Vec<String> = diff_fleet(&before, &after)
+            .iter()
+            .map(FleetChange::describe)
+            .collect();
+
+        assert_eq!(live_count(&before), 1);
+        assert_eq!(actual_result, vec![" + au3.nordvpn.com: 3.3.3.3"]);
+    }
+
+    #[test]
+    fn when_nothing_changed_should_return_empty() {
+        let before = fleet(&[("au", 1, [1, 1, 1, 1])]);
+
+        assert!(diff_fleet(&before, &before.clone()).is_empty());
+    }
+
+    #[test]
+    fn when_changed_should_serialize_before_and_after() {
+        let test_change = FleetChange::Changed(
+            String::from("au2.nordvpn.com"),
+            vec![Ipv4Addr::new(2, 2, 2, 2)],
+            vec![Ipv4Addr::new(2, 2, 2, 9)],
+        );
+
+        assert_eq!(
+            test_change.to_json(),
+            "{\"type\":\"changed\",\"host\":\"au2.nordvpn.com\",\"before\":[\"2.2.2.2\"],\"after\":[\"2.2.2.9\"]}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod parse_interval_tests {
+    use super::*;
+
+    #[test]
+    fn when_units_should_add_them_up() {
+        assert_eq!(parse_interval("1h").unwrap(), Duration::from_secs(3600));
+        assert_eq!(parse_interval("1h30m").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_interval("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_interval("45").unwrap(), Duration::from_secs(45));
+    }
+
+    #[test]
+    fn when_malformed_should_throw_error() {
+        assert!(parse_interval("1d").is_err());
+        assert!(parse_interval("h").is_err());
+        assert!(parse_interval("1h30").is_err());
+        assert!(parse_interval("0m").is_err());
+    }
+
+    #[test]
+    fn when_zero_should_throw_error() {
+        assert!(parse_interval("0").is_err());
+        assert!(parse_interval("0s").is_err());
+        assert!(parse_interval("0h0m").is_err());
+    }
+}
+
+#[cfg(test)]
+mod parse_webhook_url_tests {
+    use super::*;
+
+    #[test]
+    fn when_http_url_should_split_address_and_path() {
+        assert_eq!(
+            parse_webhook_url("http://127.0.0.1:9000/hooks/fns").unwrap(),
+            (
+                String::from("127.0.0.1:9000"),
+                String::from("127.0.0.1:9000"),
+                String::from("/hooks/fns")
+            )
+        );
+        assert_eq!(
+            parse_webhook_url("http://alerts.local").unwrap().0,
+            "alerts.local:80"
+        );
+    }
+
+    #[test]
+    fn when_not_http_should_throw_error() {
+        assert!(parse_webhook_url("https://alerts.local/hook").is_err());
+        assert!(parse_webhook_url("http:///hook").is_err());
+    }
+}
+
+#[cfg(test)]
+mod post_webhook_tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    // Util: accept one POST on the returned address, answer it with status & hand back the request line,
+    // headers & body
+    fn webhook_stand_in(
+        status: &'static str,
+    ) -> (String, thread::JoinHandle<(String, Vec<String>, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut headers = Vec::new();
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                headers.push(header.trim_end().to_string());
+            }
+            let length: usize = headers
+                .iter()
+                .find_map(|header| header.strip_prefix("Content-Length: "))
+                .unwrap()
+                .parse()
+                .unwrap();
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            write!(&stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status).unwrap();
+            (
+                request_line.trim_end().to_string(),
+                headers,
+                String::from_utf8(body).unwrap(),
+            )
+        });
+        (address, handle)
+    }
+
+    #[test]
+    fn when_posted_should_send_changes_as_json() {
+        let (test_address, test_handle) = webhook_stand_in("204 No Content");
+        let test_changes = vec![FleetChange::Changed(
+            String::from("au12.nordvpn.com"),
+            vec![Ipv4Addr::new(1, 2, 3, 4)],
+            vec![Ipv4Addr::new(1, 2, 3, 5)],
+        )];
+
+        let actual_result = post_webhook(
+            &format!("http://{}/hooks/fns", test_address),
+            &[CountryCode::Au, CountryCode::De],
+            &test_changes,
+        );
+
+        let (request_line, headers, body) = test_handle.join().unwrap();
+        assert!(actual_result.is_ok());
+        assert_eq!(request_line, "POST /hooks/fns HTTP/1.1");
+        assert!(headers.contains(&format!("Host: {}", test_address)));
+        assert!(headers.contains(&String::from("Content-Type: application/json")));
+        assert!(headers.contains(&String::from("Connection: close")));
+        assert!(body.starts_with("{\"countries\":[\"au\",\"de\"],\"checked\":"));
+        assert!(body.ends_with(
+            ",\"changes\":[{\"type\":\"changed\",\"host\":\"au12.nordvpn.com\",\"before\":[\"1.2.3.4\"],\"after\":[\"1.2.3.5\"]}]}"
+        ));
+    }
+
+    #[test]
+    fn when_not_2xx_should_throw_error() {
+        let (test_address, test_handle) = webhook_stand_in("500 Internal Server Error");
+
+        let actual_result = post_webhook(
+            &format!("http://{}/", test_address),
+            &[CountryCode::Au],
+            &[],
+        );
+
+        test_handle.join().unwrap();
+        assert!(actual_result.is_err());
+    }
+}
@@ -2,9 +2,20 @@
  *  Copyright (c) Spudmash Media Pty Ltd
  *  Licensed under the MIT License. See License.md in the project root for license information.
  *--------------------------------------------------------------------------------------------*/
+use super::adaptive_concurrency::AdaptiveConcurrency;
+use super::checkpoint::Checkpoint;
+use super::dns_resolver::DnsResolver;
 use super::enum_country_code::CountryCode;
+use super::enum_lookup_outcome::LookupOutcome;
 use super::enum_match_type::MatchType;
+use super::progress::ScanProgress;
+use super::scheduling::{jitter_delay, RateLimiter};
+use std::collections::BTreeSet;
 use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
 pub struct WorkerRequest {
     pub country_code: CountryCode,
@@ -13,19 +24,243 @@ pub struct WorkerRequest {
 
 impl WorkerRequest {
     pub fn new(country_code: CountryCode, data: Vec<u32>) -> WorkerRequest {
-        WorkerRequest {
-            country_code: country_code,
-            data: data,
-        }
+        WorkerRequest { country_code, data }
     }
 }
 
+#[derive(Clone)]
 pub struct WorkerResponse {
+    pub country_code: CountryCode,
+    pub num: u32,
     pub match_type: MatchType,
     pub host: String,
-    pub ip: Ipv4Addr,
+    pub ip: Option<Ipv4Addr>, // the record that matched
+    pub outcome: LookupOutcome,
 }
 
+#[derive(Clone)]
 pub struct SearchFilter {
-    pub ip: Ipv4Addr,
+    pub ips: Vec<Ipv4Addr>,
+    pub first: bool, // stop once every ip has an exact match
+}
+
+impl SearchFilter {
+    // early exit is the default when searching for a single ip
+    pub fn new(ips: Vec<Ipv4Addr>) -> SearchFilter {
+        let first = ips.len() == 1;
+        SearchFilter { ips, first }
+    }
+}
+
+// Shared by all workers so a search can stop before the dataframe is exhausted
+#[derive(Default)]
+pub struct ScanControl {
+    stopped: AtomicBool,
+    interrupted: AtomicBool,
+    paused: AtomicBool,
+    found: Mutex<Vec<Ipv4Addr>>,
+}
+
+impl ScanControl {
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    // stop requested by the user (Ctrl-C) rather than by an early exit
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::SeqCst);
+        self.stop();
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    // Block while paused, returns early if the search is stopped
+    pub fn wait_while_paused(&self) {
+        while self.is_paused() && !self.is_stopped() {
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    // record an exact match, true once every searched ip has been found
+    pub fn record_exact(&self, ip: Ipv4Addr, filter: &SearchFilter) -> bool {
+        let mut found = self.found.lock().unwrap();
+        if !found.contains(&ip) {
+            found.push(ip);
+        }
+
+        filter.ips.iter().all(|target| found.contains(target))
+    }
+}
+
+// Per-lookup timeout, retry & scheduling policy
+#[derive(Clone)]
+pub struct LookupPolicy {
+    pub timeout: Duration,
+    pub retries: u32,
+    pub backoff: Duration,
+    pub rate_limiter: Option<Arc<RateLimiter>>, // shared by every clone of the policy
+    pub jitter: Duration,
+}
+
+impl LookupPolicy {
+    pub fn new(timeout: Duration, retries: u32) -> LookupPolicy {
+        LookupPolicy {
+            timeout,
+            retries,
+            backoff: Duration::from_millis(500),
+            rate_limiter: None,
+            jitter: Duration::from_millis(0),
+        }
+    }
+
+    // wait for a --rate token, then a random --jitter delay, before each query
+    pub fn throttle(&self, control: &ScanControl) {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire(control);
+        }
+        if self.jitter.as_micros() > 0 {
+            thread::sleep(jitter_delay(self.jitter));
+        }
+    }
+
+    // exponential backoff before retry attempt n (1-based): backoff, 2 x backoff, 4 x backoff...
+    pub fn backoff_delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        self.backoff * 2u32.pow(exponent)
+    }
+}
+
+// Edge of a country's fleet, found while workers sweep its numbers upwards (--max-gap)
+// - a number is past the edge once the max_gap numbers after the highest live host are all NXDOMAIN
+// - gaps are counted in positions of the scanned numbers, so sparse ranges work too
+pub struct FleetEdge {
+    pub country_code: CountryCode,
+    numbers: Vec<u32>, // sorted
+    max_gap: usize,
+    state: Mutex<FleetEdgeState>,
+    skipped: AtomicUsize,
+}
+
+#[derive(Default)]
+struct FleetEdgeState {
+    highest_live: Option<usize>,
+    misses: BTreeSet<usize>, // NXDOMAIN positions past highest_live
+}
+
+impl FleetEdge {
+    pub fn new(country_code: CountryCode, mut numbers: Vec<u32>, max_gap: u32) -> FleetEdge {
+        numbers.sort_unstable();
+        FleetEdge {
+            country_code,
+            numbers,
+            max_gap: max_gap as usize,
+            state: Mutex::new(FleetEdgeState::default()),
+            skipped: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn record(&self, num: u32, outcome: &LookupOutcome) {
+        let position = match self.numbers.binary_search(&num) {
+            Ok(position) => position,
+            Err(_) => return,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if state
+            .highest_live
+            .is_some_and(|highest| position <= highest)
+        {
+            return;
+        }
+        match outcome {
+            LookupOutcome::Resolved(_) => {
+                state.highest_live = Some(position);
+                state.misses = state.misses.split_off(&(position + 1));
+            }
+            LookupOutcome::NxDomain => {
+                state.misses.insert(position);
+            }
+            // timeouts & errors are unknown, they neither extend nor close the gap
+            LookupOutcome::Timeout | LookupOutcome::Error(_) => {}
+        }
+    }
+
+    pub fn is_past_edge(&self, num: u32) -> bool {
+        let position = match self.numbers.binary_search(&num) {
+            Ok(position) | Err(position) => position,
+        };
+
+        let state = self.state.lock().unwrap();
+        let gap_start = state.highest_live.map_or(0, |highest| highest + 1);
+        let gap_end = gap_start + self.max_gap;
+
+        position >= gap_end && state.misses.range(gap_start..gap_end).count() == self.max_gap
+    }
+
+    pub fn highest_live(&self) -> Option<u32> {
+        let state = self.state.lock().unwrap();
+        state.highest_live.map(|position| self.numbers[position])
+    }
+
+    // hostnames dropped by workers once they went past the edge
+    pub fn skip(&self, count: usize) {
+        self.skipped.fetch_add(count, Ordering::SeqCst);
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.skipped.load(Ordering::SeqCst)
+    }
+}
+
+// How many lookups run at once
+#[derive(Clone, Copy)]
+pub enum Concurrency {
+    Fixed(usize),    // one thread per lookup in flight
+    Adaptive(usize), // up to this many, tuned from observed latency & error rate
+}
+
+impl Concurrency {
+    pub fn threads(&self) -> usize {
+        match *self {
+            Concurrency::Fixed(threads) | Concurrency::Adaptive(threads) => threads,
+        }
+    }
+}
+
+// Everything a worker needs besides its dataframe, shared by all workers
+pub struct WorkerContext {
+    pub filter: SearchFilter,
+    pub resolver: DnsResolver,
+    pub policy: LookupPolicy,
+    pub control: Arc<ScanControl>,
+    pub checkpoint: Option<Checkpoint>,
+    pub edges: Vec<FleetEdge>, // one per country with --max-gap, empty otherwise
+    pub adaptive: Option<AdaptiveConcurrency>,
+    pub progress: Box<dyn ScanProgress>,
+}
+
+impl WorkerContext {
+    pub fn fleet_edge(&self, country_code: &CountryCode) -> Option<&FleetEdge> {
+        self.edges
+            .iter()
+            .find(|edge| edge.country_code.to_string() == country_code.to_string())
+    }
 }
@@ -0,0 +1,27 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Spudmash Media Pty Ltd
+ *  Licensed under the MIT License. See License.md in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+mod core;
+
+//
+// fns:  Find NordVPN Server, as a library
+//
+// let results = fns::Scan::builder()
+//     .country(fns::CountryCode::Au)
+//     .range(1..=1000)
+//     .target("1.2.3.4".parse().unwrap())
+//     .build()?
+//     .run()?;
+//
+pub use crate::core::enum_country_code::CountryCode;
+pub use crate::core::enum_exit_code::ExitCode;
+pub use crate::core::{
+    build_data, daemon_socket_path, entry_point_bench, entry_point_daemon, entry_point_serve,
+    entry_point_shell, entry_point_tui, entry_point_watch, is_in_same_subnet, is_same_ip,
+    is_valid_ip, parse_country_list, parse_interval, parse_range_list, read_range_file,
+    verbose_info, write_csv, CachedHost, Checkpoint, Concurrency, DnsResolver, HostCache,
+    IndicatifProgress, Interrupt, InterruptListener, LogProgress, LookupOutcome, LookupPolicy,
+    LookupSummary, MatchPrinter, MatchType, NoProgress, Output, RateLimiter, ResolvedHost, Scan,
+    ScanBuilder, ScanEvent, ScanHandle, ScanProgress, ScanResults, SearchFilter, WorkerResponse,
+};
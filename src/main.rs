@@ -2,10 +2,345 @@
  *  Copyright (c) Spudmash Media Pty Ltd
  *  Licensed under the MIT License. See License.md in the project root for license information.
  *--------------------------------------------------------------------------------------------*/
-use clap::{App, Arg};
-use num_cpus;
+use clap::{App, AppSettings, Arg, ArgMatches, ErrorKind, SubCommand};
+use fns::{CountryCode, ExitCode};
 use std::net::Ipv4Addr;
-mod core;
+use std::os::unix::net::UnixStream;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Util: parse an option value, exit with ExitCode::Error if it is malformed
+fn parse_or_exit<T: FromStr>(value: &str, name: &str) -> T {
+    value.parse::<T>().unwrap_or_else(|_| {
+        eprintln!("error: Invalid value for '{}': {}", name, value);
+        std::process::exit(ExitCode::Error.code())
+    })
+}
+
+// Util: unwrap a setup result, exit with ExitCode::Error if it failed
+fn unwrap_or_exit<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        std::process::exit(ExitCode::Error.code())
+    })
+}
+
+// Util: Ctrl-C stops whatever is running through the returned interrupt, and quits when
+// nothing is listening for it (E.g. a second Ctrl-C while a search is still stopping)
+fn interrupt_on_ctrlc() -> fns::Interrupt {
+    let interrupt = fns::Interrupt::default();
+    let handler = interrupt.clone();
+    if let Err(err) = ctrlc::set_handler(move || {
+        if !handler.trigger() {
+            std::process::exit(ExitCode::Error.code());
+        }
+    }) {
+        eprintln!("warning: Unable to install Ctrl-C handler: {}", err);
+    }
+    interrupt
+}
+
+// Util: a --rate value, E.g. 50/s
+fn parse_rate(value: &str) -> Result<u32, String> {
+    match value.trim_end_matches("/s").parse::<u32>() {
+        Ok(0) => Err(String::from("Rate must be at least 1/s")),
+        Ok(rate) => Ok(rate),
+        Err(_) => Err(format!("Invalid value for 'Rate': {}", value)),
+    }
+}
+
+// --resolver, bench takes it on its own
+fn resolver_arg() -> Arg<'static, 'static> {
+    Arg::with_name("Resolver")
+        .takes_value(true)
+        .long("resolver")
+        .help("DNS server to query, E.g. 1.1.1.1 or 1.1.1.1:53 [Default: first nameserver in /etc/resolv.conf]")
+}
+
+// --no-cache, except for serve which always answers from a cache
+fn no_cache_arg() -> Arg<'static, 'static> {
+    Arg::with_name("No Cache")
+        .long("no-cache")
+        .help("Don't read or update the cache of resolved hostnames")
+}
+
+// Options shared by the search & the subcommands that sweep: -t, --timeout, --retries & --resolver
+fn lookup_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("Thread Count")
+            .takes_value(true)
+            .short("t")
+            .long("threadcount")
+            .help("Thread Count [Default to number of logical CPU cores]"),
+        Arg::with_name("Timeout")
+            .takes_value(true)
+            .long("timeout")
+            .help("Seconds to wait for each lookup before retrying [Default: 5]"),
+        Arg::with_name("Retries")
+            .takes_value(true)
+            .long("retries")
+            .help(
+            "Retries per lookup after a timeout or error, with exponential backoff [Default: 2]",
+        ),
+        resolver_arg(),
+    ]
+}
+
+// Util: -t, or the number of logical CPU cores
+// - with --adaptive the thread count is only a ceiling, so it defaults much higher
+fn concurrency_from(matches: &ArgMatches) -> fns::Concurrency {
+    let adaptive = matches.is_present("Adaptive");
+    let tc = match (matches.value_of("Thread Count"), adaptive) {
+        (Some(value), _) => parse_or_exit::<usize>(value, "Thread Count"),
+        (None, true) => 64,
+        (None, false) => num_cpus::get(),
+    };
+    match adaptive {
+        true => fns::Concurrency::Adaptive(tc),
+        false => fns::Concurrency::Fixed(tc),
+    }
+}
+
+// Util: --timeout & --retries, plus --rate & --jitter where they are offered
+fn lookup_policy_from(matches: &ArgMatches) -> fns::LookupPolicy {
+    let timeout = parse_or_exit::<u64>(matches.value_of("Timeout").unwrap_or("5"), "Timeout");
+    let retries = parse_or_exit::<u32>(matches.value_of("Retries").unwrap_or("2"), "Retries");
+    let mut policy = fns::LookupPolicy::new(Duration::from_secs(timeout), retries);
+    if let Some(value) = matches.value_of("Rate") {
+        let rate = unwrap_or_exit(parse_rate(value));
+        policy.rate_limiter = Some(Arc::new(fns::RateLimiter::new(rate)));
+    }
+    if let Some(value) = matches.value_of("Jitter") {
+        policy.jitter = Duration::from_millis(parse_or_exit::<u64>(value, "Jitter"));
+    }
+    policy
+}
+
+// Util: the --resolver of a subcommand, or the system nameserver
+fn resolver_or_exit(matches: &ArgMatches) -> fns::DnsResolver {
+    unwrap_or_exit(match matches.value_of("Resolver") {
+        Some(address) => fns::DnsResolver::from_address(address),
+        None => fns::DnsResolver::from_system(),
+    })
+}
+
+// Util: the default cache file of a subcommand, unless it has --no-cache
+fn cache_path_of(matches: &ArgMatches) -> Option<String> {
+    if matches.is_present("No Cache") {
+        None
+    } else {
+        fns::HostCache::default_path()
+    }
+}
+
+// fns bench: resolver throughput at several thread counts
+fn bench_command(matches: &ArgMatches) -> ExitCode {
+    let p_country_code = "Country Code";
+    let p_sample = "Sample";
+    let p_levels = "Levels";
+    let p_timeout = "Timeout";
+
+    let country_code = parse_or_exit::<CountryCode>(
+        matches.value_of(p_country_code).unwrap_or("au"),
+        p_country_code,
+    );
+
+    let sample = parse_or_exit::<u32>(matches.value_of(p_sample).unwrap_or("200"), p_sample);
+
+    let levels: Vec<usize> = matches
+        .value_of(p_levels)
+        .unwrap_or("1,2,4,8,16,32")
+        .split(',')
+        .map(|value| parse_or_exit::<usize>(value.trim(), p_levels))
+        .collect();
+
+    let timeout = parse_or_exit::<u64>(matches.value_of(p_timeout).unwrap_or("5"), p_timeout);
+
+    // no retries, so the error rate shows what the resolver really does
+    let policy = fns::LookupPolicy::new(Duration::from_secs(timeout), 0);
+
+    let resolver = resolver_or_exit(matches);
+
+    match fns::entry_point_bench(country_code, sample, levels, resolver, policy) {
+        Ok(_) => ExitCode::Success,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::Error
+        }
+    }
+}
+
+// fns tui: interactive sweep of one country
+fn tui_command(matches: &ArgMatches, interrupt: &fns::Interrupt) -> ExitCode {
+    let p_country_code = "Country Code";
+    let p_ip_address = "Ip4 Address";
+    let p_range = "Range";
+
+    let country_code = parse_or_exit::<CountryCode>(
+        matches.value_of(p_country_code).unwrap_or("au"),
+        p_country_code,
+    );
+
+    let target = parse_or_exit::<Ipv4Addr>(matches.value_of(p_ip_address).unwrap(), p_ip_address);
+
+    let numbers = unwrap_or_exit(fns::parse_range_list(
+        matches.value_of(p_range).unwrap_or("1-1000"),
+    ));
+
+    match fns::entry_point_tui(
+        country_code,
+        numbers,
+        target,
+        concurrency_from(matches),
+        resolver_or_exit(matches),
+        lookup_policy_from(matches),
+        cache_path_of(matches),
+        interrupt.clone(),
+    ) {
+        Ok(results) => results.exit_code(),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::Error
+        }
+    }
+}
+
+// fns shell: queries against hostname tables kept in memory
+fn shell_command(matches: &ArgMatches, interrupt: &fns::Interrupt) -> ExitCode {
+    let p_country_code = "Country Code";
+    let p_range = "Range";
+
+    let countries = unwrap_or_exit(fns::parse_country_list(
+        matches.value_of(p_country_code).unwrap_or("au"),
+    ));
+
+    let numbers = unwrap_or_exit(fns::parse_range_list(
+        matches.value_of(p_range).unwrap_or("1-1000"),
+    ));
+
+    match fns::entry_point_shell(
+        countries,
+        numbers,
+        concurrency_from(matches),
+        resolver_or_exit(matches),
+        lookup_policy_from(matches),
+        cache_path_of(matches),
+        interrupt.clone(),
+    ) {
+        Ok(_) => ExitCode::Success,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::Error
+        }
+    }
+}
+
+// fns serve: JSON over HTTP, answered from the cache
+fn serve_command(matches: &ArgMatches) -> ExitCode {
+    let p_listen = "Listen";
+    let p_sweep = "Sweep";
+    let p_range = "Range";
+    let p_cache = "Cache File";
+
+    let numbers = unwrap_or_exit(fns::parse_range_list(
+        matches.value_of(p_range).unwrap_or("1-1000"),
+    ));
+
+    let sweep = match matches.value_of(p_sweep) {
+        Some(value) => unwrap_or_exit(fns::parse_country_list(value)),
+        None => Vec::new(),
+    };
+
+    let cache_path = unwrap_or_exit(
+        matches
+            .value_of(p_cache)
+            .map(String::from)
+            .or_else(fns::HostCache::default_path)
+            .ok_or_else(|| String::from("No cache file, set --cache or $HOME")),
+    );
+
+    match fns::entry_point_serve(
+        matches.value_of(p_listen).unwrap_or("127.0.0.1:8080"),
+        numbers,
+        sweep,
+        concurrency_from(matches),
+        resolver_or_exit(matches),
+        lookup_policy_from(matches),
+        cache_path,
+    ) {
+        Ok(_) => ExitCode::Success,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::Error
+        }
+    }
+}
+
+// fns daemon: owns the cache & every lookup, shared by searches on this machine
+fn daemon_command(matches: &ArgMatches, interrupt: &fns::Interrupt) -> ExitCode {
+    let p_socket = "Socket";
+    let p_ttl = "Ttl";
+
+    let ttl = parse_or_exit::<u64>(matches.value_of(p_ttl).unwrap_or("600"), p_ttl);
+
+    match fns::entry_point_daemon(
+        &matches
+            .value_of(p_socket)
+            .map(String::from)
+            .unwrap_or_else(fns::daemon_socket_path),
+        concurrency_from(matches),
+        resolver_or_exit(matches),
+        lookup_policy_from(matches),
+        cache_path_of(matches),
+        Duration::from_secs(ttl),
+        interrupt.clone(),
+    ) {
+        Ok(_) => ExitCode::Success,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::Error
+        }
+    }
+}
+
+// fns watch: re-resolve the fleet on an interval & report what changed
+fn watch_command(matches: &ArgMatches, interrupt: &fns::Interrupt) -> ExitCode {
+    let p_country_code = "Country Code";
+    let p_interval = "Interval";
+    let p_range = "Range";
+    let p_webhook = "Webhook";
+
+    let countries = unwrap_or_exit(fns::parse_country_list(
+        matches.value_of(p_country_code).unwrap_or("au"),
+    ));
+
+    let interval = unwrap_or_exit(fns::parse_interval(
+        matches.value_of(p_interval).unwrap_or("1h"),
+    ));
+
+    let numbers = unwrap_or_exit(fns::parse_range_list(
+        matches.value_of(p_range).unwrap_or("1-1000"),
+    ));
+
+    match fns::entry_point_watch(
+        countries,
+        numbers,
+        interval,
+        matches.value_of(p_webhook).map(String::from),
+        concurrency_from(matches),
+        resolver_or_exit(matches),
+        lookup_policy_from(matches),
+        cache_path_of(matches),
+        interrupt.clone(),
+    ) {
+        Ok(_) => ExitCode::Success,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::Error
+        }
+    }
+}
 
 //
 // fns:  Find NordVPN Server
@@ -13,21 +348,212 @@ mod core;
 fn main() {
     let p_start_num = "Start Number";
     let p_end_num = "End Number";
+    let p_range = "Range";
+    let p_range_file = "Range File";
+    let p_auto_range = "Auto Range";
+    let p_gap_tolerance = "Gap Tolerance";
+    let p_max_gap = "Max Gap";
+    let p_rate = "Rate";
+    let p_jitter = "Jitter";
+    let p_shuffle = "Shuffle";
+    let p_cache = "Cache File";
+    let p_no_cache = "No Cache";
+    let p_no_daemon = "No Daemon";
     let p_ip_address = "Ip4 Address";
     let p_country_code = "Country Code";
     let p_thread_count = "Thread Count";
+    let p_adaptive = "Adaptive";
     let p_verbose = "Verbose";
+    let p_quiet = "Quiet";
+    let p_porcelain = "Porcelain";
+    let p_per_worker_progress = "Per Worker Progress";
+    let p_timeout = "Timeout";
+    let p_retries = "Retries";
+    let p_resolver = "Resolver";
+    let p_checkpoint = "Checkpoint File";
+    let p_resume = "Resume File";
+    let p_first = "First";
+    let p_all = "All";
 
     let matches = App::new("fns")
         .version("1.1")
         .author("Spudmash Media [ - ]")
         .about("Reverse Lookup of NordVPN Server hostname by Ipv4 address\nBuilt with Rust 🦀")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Measure resolver throughput & latency at several thread counts")
+                .arg(
+                    Arg::with_name("Country Code")
+                        .takes_value(true)
+                        .short("c")
+                        .long("country")
+                        .help("Country of the sample hostnames [Default: au]"),
+                )
+                .arg(
+                    Arg::with_name("Sample")
+                        .takes_value(true)
+                        .long("sample")
+                        .help("Hostnames resolved at each thread count [Default: 200]"),
+                )
+                .arg(
+                    Arg::with_name("Levels")
+                        .takes_value(true)
+                        .long("levels")
+                        .help("Thread counts to measure [Default: 1,2,4,8,16,32]"),
+                )
+                .arg(
+                    Arg::with_name("Timeout")
+                        .takes_value(true)
+                        .long("timeout")
+                        .help("Seconds to wait for each lookup [Default: 5]"),
+                )
+                .arg(resolver_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("tui")
+                .about("Interactive sweep of one country: live results, pause, cancel, change target & export")
+                .args(&lookup_args())
+                .arg(no_cache_arg())
+                .arg(
+                    Arg::with_name("Country Code")
+                        .takes_value(true)
+                        .short("c")
+                        .long("country")
+                        .help("Country to sweep [Default: au]"),
+                )
+                .arg(
+                    Arg::with_name("Ip4 Address")
+                        .takes_value(true)
+                        .short("i")
+                        .long("ip")
+                        .validator(fns::is_valid_ip)
+                        .help("IP address to search for, can be changed while running")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("Range")
+                        .takes_value(true)
+                        .short("r")
+                        .long("range")
+                        .help("Server numbers to scan, E.g. 1-100,250 [Default: 1-1000]"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("shell")
+                .about("Prompt for find, country, neighbors & export queries, swept hostnames stay in memory between them")
+                .args(&lookup_args())
+                .arg(no_cache_arg())
+                .arg(
+                    Arg::with_name("Country Code")
+                        .takes_value(true)
+                        .short("c")
+                        .long("country")
+                        .help("Countries to start with, E.g. au,de or all [Default: au]"),
+                )
+                .arg(
+                    Arg::with_name("Range")
+                        .takes_value(true)
+                        .short("r")
+                        .long("range")
+                        .help("Server numbers to sweep in each country, E.g. 1-100,250 [Default: 1-1000]"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Answer lookups over HTTP as JSON from the cache: GET /lookup?ip=&country= & /hosts/{country}")
+                .args(&lookup_args())
+                .arg(
+                    Arg::with_name("Listen")
+                        .takes_value(true)
+                        .long("listen")
+                        .help("Address to listen on [Default: 127.0.0.1:8080]"),
+                )
+                .arg(
+                    Arg::with_name("Sweep")
+                        .takes_value(true)
+                        .long("sweep")
+                        .help("Countries a lookup may sweep when the cache has no exact match, E.g. au,de or all [Default: none, answer from the cache only]"),
+                )
+                .arg(
+                    Arg::with_name("Range")
+                        .takes_value(true)
+                        .short("r")
+                        .long("range")
+                        .help("Server numbers swept when the cache can't answer a lookup, E.g. 1-100,250 [Default: 1-1000]"),
+                )
+                .arg(
+                    Arg::with_name("Cache File")
+                        .takes_value(true)
+                        .long("cache")
+                        .help("Cache of resolved hostnames to answer from [Default: ~/.cache/fns/hosts.tsv]"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("daemon")
+                .about("Own the cache & every lookup on a Unix socket, searches use it when it's running & share results")
+                .args(&lookup_args())
+                .arg(no_cache_arg())
+                .arg(
+                    Arg::with_name("Socket")
+                        .takes_value(true)
+                        .long("socket")
+                        .help("Unix socket to listen on [Default: $FNS_SOCKET, $XDG_RUNTIME_DIR/fns.sock or /tmp/fns-<uid>/fns.sock]"),
+                )
+                .arg(
+                    Arg::with_name("Rate")
+                        .takes_value(true)
+                        .long("rate")
+                        .help("Limit lookups per second across every search, E.g. 50/s"),
+                )
+                .arg(
+                    Arg::with_name("Ttl")
+                        .takes_value(true)
+                        .long("ttl")
+                        .help("Seconds a resolved hostname is shared before it is looked up again [Default: 600]"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("watch")
+                .about("Re-resolve the fleet on an interval & report hostnames whose IP changed, appeared or disappeared")
+                .args(&lookup_args())
+                .arg(no_cache_arg())
+                .arg(
+                    Arg::with_name("Country Code")
+                        .takes_value(true)
+                        .short("c")
+                        .long("country")
+                        .help("Countries to watch, E.g. au,de or all [Default: au]"),
+                )
+                .arg(
+                    Arg::with_name("Interval")
+                        .takes_value(true)
+                        .long("interval")
+                        .help("Time between sweeps, E.g. 1h, 30m or 90s [Default: 1h]"),
+                )
+                .arg(
+                    Arg::with_name("Range")
+                        .takes_value(true)
+                        .short("r")
+                        .long("range")
+                        .help("Server numbers to sweep in each country, E.g. 1-100,250 [Default: 1-1000]"),
+                )
+                .arg(
+                    Arg::with_name("Webhook")
+                        .takes_value(true)
+                        .long("webhook")
+                        .help("POST the changes of each sweep as JSON to this http:// url"),
+                ),
+        )
         .arg(
             Arg::with_name(p_country_code)
                 .takes_value(true)
                 .short("c")
                 .long("country")
-                .help("Options: [Al, Ar, Au, At, Be, Ba, Br, Bg, Ca, Cl, Cr, Hr, Cy, Cz, Dk, Ee, Fi, Fr, Ge, De, Gr, Hk, Hu, Is, In, Id, Ie, Il, It, Jp, Lv, Lu, My, Mx, Md, Nl, Nz, Mk, No, Pl, Pt, Ro, Rs, Sg, Sk, Si, Za, Kr, Es, Se, Ch, Tw, Th, Tr, Ua, Uk, Us, Vn]")
+                .multiple(true)
+                .use_delimiter(true)
+                .number_of_values(1)
+                .help("Options: [All, Al, Ar, Au, At, Be, Ba, Br, Bg, Ca, Cl, Cr, Hr, Cy, Cz, Dk, Ee, Fi, Fr, Ge, De, Gr, Hk, Hu, Is, In, Id, Ie, Il, It, Jp, Lv, Lu, My, Mx, Md, Nl, Nz, Mk, No, Pl, Pt, Ro, Rs, Sg, Sk, Si, Za, Kr, Es, Se, Ch, Tw, Th, Tr, Ua, Uk, Us, Vn]")
                 .required(true),
         )
         .arg(
@@ -48,21 +574,130 @@ fn main() {
                 .help("Default: 1000")
                 .required(false),
         )
+        .arg(
+            Arg::with_name(p_range)
+                .takes_value(true)
+                .short("r")
+                .long("range")
+                .help("Server numbers to scan instead of --start/--end, E.g. 1-100,250,500-800")
+                .conflicts_with_all(&[p_start_num, p_end_num])
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(p_range_file)
+                .takes_value(true)
+                .long("range-file")
+                .help("Read server numbers to scan from a file, one number or range per line")
+                .conflicts_with_all(&[p_start_num, p_end_num])
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(p_auto_range)
+                .long("auto-range")
+                .help("Discover the highest live server number of each country before scanning, instead of --end")
+                .conflicts_with_all(&[p_end_num, p_range, p_range_file])
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(p_gap_tolerance)
+                .takes_value(true)
+                .long("gap-tolerance")
+                .help("Consecutive NXDOMAINs --auto-range steps over before treating a number as past the last server [Default: 10]")
+                .requires(p_auto_range)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(p_max_gap)
+                .takes_value(true)
+                .long("max-gap")
+                .help("Stop scanning a country after this many consecutive NXDOMAINs past its highest live server")
+                .required(false),
+        )
         .arg(
             Arg::with_name(p_ip_address)
                 .takes_value(true)
                 .short("i")
                 .long("ip")
-                .help("Search for VPN Hostname by IP address. E.g. 127.0.0.1")
+                .help("Search for VPN Hostname by IP address. E.g. 127.0.0.1 or 1.2.3.4,5.6.7.8")
                 .required(true)
-                .validator(core::is_valid_ip),
+                .multiple(true)
+                .use_delimiter(true)
+                .number_of_values(1)
+                .validator(fns::is_valid_ip),
+        )
+        .arg(
+            Arg::with_name(p_first)
+                .long("first")
+                .help("Stop searching once every IP has an exact match [Default when searching for a single IP]")
+                .conflicts_with(p_all)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(p_all)
+                .long("all")
+                .help("Scan the whole range, even after an exact match")
+                .required(false),
+        )
+        .args(&lookup_args())
+        .arg(
+            Arg::with_name(p_adaptive)
+                .long("adaptive")
+                .help("Tune lookups in flight from observed latency & error rate, up to the thread count [Default: 64]")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(p_rate)
+                .takes_value(true)
+                .long("rate")
+                .help("Limit lookups per second across all threads, E.g. 50/s")
+                .required(false),
         )
         .arg(
-            Arg::with_name(p_thread_count)
+            Arg::with_name(p_jitter)
                 .takes_value(true)
-                .short("t")
-                .long("threadcount")
-                .help("Thread Count [Default to number of physical CPU cores]")
+                .long("jitter")
+                .help("Wait a random 0 to N milliseconds before each lookup")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(p_shuffle)
+                .long("shuffle")
+                .help("Scan hostnames in random order")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(p_checkpoint)
+                .takes_value(true)
+                .long("checkpoint")
+                .help("Save completed lookups to a state file, so an interrupted search can be resumed")
+                .conflicts_with(p_resume)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(p_resume)
+                .takes_value(true)
+                .long("resume")
+                .help("Resume a search from a state file, skipping hostnames already scanned")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(p_cache)
+                .takes_value(true)
+                .long("cache")
+                .help("Cache of resolved hostnames, used to scan likely matches first [Default: ~/.cache/fns/hosts.tsv]")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(p_no_daemon)
+                .long("no-daemon")
+                .help("Search locally even when a fns daemon is running")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(p_no_cache)
+                .long("no-cache")
+                .help("Don't read or update the cache of resolved hostnames")
+                .conflicts_with(p_cache)
                 .required(false),
         )
         .arg(
@@ -72,49 +707,206 @@ fn main() {
                 .help("Verbose mode will print out CPU information & suggestions")
                 .required(false),
         )
-        .get_matches();
-
-    let start_num = matches
-        .value_of(&p_start_num)
-        .unwrap_or("1")
-        .parse::<u32>()
-        .unwrap();
-
-    let end_num = matches
-        .value_of(&p_end_num)
-        .unwrap_or("1000")
-        .parse::<u32>()
-        .unwrap();
-
-    let match_ip = matches
-        .value_of(&p_ip_address)
-        .unwrap_or("127.0.0.1")
-        .parse::<Ipv4Addr>()
-        .unwrap();
-
-    let nat = matches
-        .value_of(&p_country_code)
-        .unwrap_or("none")
-        .parse::<core::enum_country_code::CountryCode>()
-        .unwrap();
-
-    let tc = matches
-        .value_of(&p_thread_count)
-        .unwrap_or(&num_cpus::get().to_string())
-        .parse::<usize>()
-        .unwrap();
-
-    if matches.is_present(&p_verbose) {
-        core::verbose_info(tc.clone());
-    }
-
-    std::process::exit(
-        match core::entry_point_mt(nat, match_ip, start_num, end_num, tc) {
-            Ok(_) => 0,
-            Err(err) => {
-                eprintln!("error: {:?}", err);
-                1
+        .arg(
+            Arg::with_name(p_quiet)
+                .short("q")
+                .long("quiet")
+                .help("Only print matches, no progress or summary")
+                .conflicts_with_all(&[p_porcelain, p_verbose])
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(p_porcelain)
+                .long("porcelain")
+                .help("Only print matches as stable tab separated lines for scripts: exact|partial, host, ip")
+                .conflicts_with(p_verbose)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(p_per_worker_progress)
+                .long("per-worker-progress")
+                .help("Show a progress bar per worker thread under the overall one")
+                .conflicts_with_all(&[p_quiet, p_porcelain])
+                .required(false),
+        )
+        .get_matches_safe()
+        .unwrap_or_else(|err| match err.kind {
+            ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => err.exit(),
+            _ => {
+                eprintln!("{}", err.message);
+                std::process::exit(ExitCode::Error.code())
             }
-        },
-    )
+        });
+
+    let interrupt = interrupt_on_ctrlc();
+
+    if let Some(bench_matches) = matches.subcommand_matches("bench") {
+        std::process::exit(bench_command(bench_matches).code())
+    }
+    if let Some(tui_matches) = matches.subcommand_matches("tui") {
+        std::process::exit(tui_command(tui_matches, &interrupt).code())
+    }
+    if let Some(shell_matches) = matches.subcommand_matches("shell") {
+        std::process::exit(shell_command(shell_matches, &interrupt).code())
+    }
+    if let Some(serve_matches) = matches.subcommand_matches("serve") {
+        std::process::exit(serve_command(serve_matches).code())
+    }
+    if let Some(daemon_matches) = matches.subcommand_matches("daemon") {
+        std::process::exit(daemon_command(daemon_matches, &interrupt).code())
+    }
+    if let Some(watch_matches) = matches.subcommand_matches("watch") {
+        std::process::exit(watch_command(watch_matches, &interrupt).code())
+    }
+
+    let start_num = parse_or_exit::<u32>(matches.value_of(p_start_num).unwrap_or("1"), p_start_num);
+
+    let end_num = parse_or_exit::<u32>(matches.value_of(p_end_num).unwrap_or("1000"), p_end_num);
+
+    let numbers = if matches.is_present(p_range) || matches.is_present(p_range_file) {
+        let mut numbers = Vec::new();
+        if let Some(range_list) = matches.value_of(p_range) {
+            numbers.extend(unwrap_or_exit(fns::parse_range_list(range_list)));
+        }
+        if let Some(path) = matches.value_of(p_range_file) {
+            numbers.extend(unwrap_or_exit(fns::read_range_file(path)));
+        }
+        numbers.sort_unstable();
+        numbers.dedup();
+        numbers
+    } else if start_num > end_num {
+        unwrap_or_exit(Err(String::from(
+            "Start Number must not be greater than End Number",
+        )))
+    } else {
+        fns::build_data(start_num, end_num)
+    };
+
+    let match_ips: Vec<Ipv4Addr> = matches
+        .values_of(p_ip_address)
+        .unwrap_or_default()
+        .map(|value| parse_or_exit::<Ipv4Addr>(value, p_ip_address))
+        .collect();
+
+    let countries: Vec<&str> = matches
+        .values_of(p_country_code)
+        .unwrap_or_default()
+        .collect();
+    let nat = unwrap_or_exit(fns::parse_country_list(&countries.join(",")));
+
+    let concurrency = concurrency_from(&matches);
+
+    let mut scan = fns::Scan::builder()
+        .countries(nat)
+        .numbers(numbers)
+        .targets(match_ips)
+        .concurrency(concurrency)
+        .policy(lookup_policy_from(&matches))
+        .shuffle(matches.is_present(p_shuffle));
+
+    // otherwise the output is detected: bars on a terminal, only matches on a piped stdout
+    if matches.is_present(p_porcelain) {
+        scan = scan.output(fns::Output::Porcelain);
+    } else if matches.is_present(p_quiet) {
+        scan = scan.output(fns::Output::Quiet);
+    } else if matches.is_present(p_per_worker_progress)
+        && fns::Output::detect() == fns::Output::Pretty
+    {
+        scan = scan.progress(fns::IndicatifProgress::new(true));
+    }
+
+    if matches.is_present(p_first) {
+        scan = scan.first(true);
+    } else if matches.is_present(p_all) {
+        scan = scan.first(false);
+    }
+
+    if let Some(address) = matches.value_of(p_resolver) {
+        scan = scan.resolver(unwrap_or_exit(fns::DnsResolver::from_address(address)));
+    }
+
+    match (matches.value_of(p_checkpoint), matches.value_of(p_resume)) {
+        (_, Some(path)) => scan = scan.checkpoint(unwrap_or_exit(fns::Checkpoint::resume(path))),
+        (Some(path), None) => scan = scan.checkpoint(unwrap_or_exit(fns::Checkpoint::create(path))),
+        (None, None) => {}
+    }
+
+    if matches.is_present(p_auto_range) {
+        scan = scan.auto_range(parse_or_exit::<u32>(
+            matches.value_of(p_gap_tolerance).unwrap_or("10"),
+            p_gap_tolerance,
+        ));
+    }
+
+    if let Some(value) = matches.value_of(p_max_gap) {
+        scan = scan.max_gap(parse_or_exit::<u32>(value, p_max_gap));
+    }
+
+    // a broken cache only costs the prioritized scan order, never the search
+    if !matches.is_present(p_no_cache) {
+        let cache_path = matches
+            .value_of(p_cache)
+            .map(String::from)
+            .or_else(fns::HostCache::default_path);
+        if let Some(path) = cache_path {
+            match fns::HostCache::load(&path) {
+                Ok(cache) => scan = scan.cache(cache),
+                Err(err) => eprintln!("warning: {}", err),
+            }
+        }
+    }
+
+    // a running daemon answers with its own resolver, threads, rate, policy & cache, and can't checkpoint,
+    // so setting any of them searches locally
+    let local_only: Vec<&str> = [
+        (p_resolver, "--resolver"),
+        (p_thread_count, "-t"),
+        (p_adaptive, "--adaptive"),
+        (p_rate, "--rate"),
+        (p_jitter, "--jitter"),
+        (p_shuffle, "--shuffle"),
+        (p_timeout, "--timeout"),
+        (p_retries, "--retries"),
+        (p_cache, "--cache"),
+        (p_no_cache, "--no-cache"),
+        (p_checkpoint, "--checkpoint"),
+        (p_resume, "--resume"),
+        (p_auto_range, "--auto-range"),
+        (p_max_gap, "--max-gap"),
+    ]
+    .iter()
+    .filter(|(name, _)| matches.is_present(name))
+    .map(|(_, flag)| *flag)
+    .collect();
+    if !matches.is_present(p_no_daemon) {
+        let socket_path = fns::daemon_socket_path();
+        if local_only.is_empty() {
+            scan = scan.daemon(&socket_path);
+        } else if UnixStream::connect(&socket_path).is_ok() {
+            eprintln!(
+                "note: searching locally, the fns daemon on {} can't apply {}",
+                socket_path,
+                local_only.join(", ")
+            );
+        }
+    }
+
+    if matches.is_present(p_verbose) {
+        fns::verbose_info(concurrency.threads());
+    }
+
+    let exit_code = match unwrap_or_exit(scan.interrupt(&interrupt).build()).run() {
+        Ok(results) => {
+            if let Some(err) = &results.cache_error {
+                eprintln!("warning: {}", err);
+            }
+            results.exit_code()
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::Error
+        }
+    };
+
+    std::process::exit(exit_code.code())
 }